@@ -0,0 +1,147 @@
+use bevy::prelude::*;
+use bevy::sprite::Rect as AtlasRect;
+use noise::{BasicMulti, MultiFractal, NoiseFn, Seedable};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use santa_game::snowflakes::{
+    baseline_snowflake_amount, build_snowflake_mesh, fill_snowflake_mesh, simulate_snowflakes,
+    spawn_snowflakes, Snowfall, SnowflakeNoise, SNOWFLAKE_LAYERS,
+};
+use santa_game::TIME_STEP;
+use std::time::{Duration, Instant};
+
+const FRAMES: u32 = 600;
+const DENSITY_FACTOR: usize = 10;
+// Simulating and batching ten times the baseline density on the outside level must take at
+// most this share of a `TIME_STEP` frame, leaving the rest for rendering and gameplay.
+const MAX_BUDGET_SHARE: f64 = 0.5;
+
+// Camera boundary of the outside level plus the spawn margin.
+fn outside_area() -> Rect<f32> {
+    Rect {
+        left: -280.0,
+        right: 280.0,
+        bottom: -115.0,
+        top: 115.0,
+    }
+}
+
+fn snowflake_atlas() -> TextureAtlas {
+    let mut atlas = TextureAtlas::new_empty(Handle::default(), Vec2::new(27.0, 13.0));
+    for &(min, max) in [
+        ((0.0, 0.0), (13.0, 13.0)),
+        ((13.0, 0.0), (20.0, 7.0)),
+        ((20.0, 0.0), (27.0, 7.0)),
+        ((13.0, 7.0), (17.0, 11.0)),
+    ]
+    .iter()
+    {
+        atlas.add_texture(AtlasRect {
+            min: Vec2::new(min.0, min.1),
+            max: Vec2::new(max.0, max.1),
+        });
+    }
+    atlas
+}
+
+fn measure<F: FnMut(u32)>(mut frame: F) -> Duration {
+    let start = Instant::now();
+    for index in 0..FRAMES {
+        frame(index);
+    }
+    start.elapsed() / FRAMES
+}
+
+// The previous update: two noise generators built every frame, three dimensional noise per flake.
+fn legacy_frame(origins: &mut [Vec2], seconds: f64) -> f32 {
+    let noise_x = BasicMulti::new()
+        .set_seed(0)
+        .set_octaves(6)
+        .set_frequency(0.02)
+        .set_lacunarity(1.5)
+        .set_persistence(0.7);
+    let noise_y = BasicMulti::new()
+        .set_seed(432627)
+        .set_octaves(6)
+        .set_frequency(0.02)
+        .set_lacunarity(1.5)
+        .set_persistence(0.7);
+
+    let mut checksum = 0.0;
+    for origin in origins.iter_mut() {
+        origin.y -= 10.0 * TIME_STEP;
+        let point = [origin.x as f64, origin.y as f64, seconds * 2.0];
+        checksum += noise_x.get(point) as f32 * 40.0 + noise_y.get(point) as f32 * 25.0;
+    }
+    checksum
+}
+
+fn main() {
+    let area = outside_area();
+    let amount = baseline_snowflake_amount(&area);
+    let atlas = snowflake_atlas();
+    let noise = SnowflakeNoise::new(0, 432627);
+    let snowfall = Snowfall {
+        area,
+        velocity: Vec2::new(5.0, -10.0),
+        turbulence: 1.0,
+    };
+    let mut rng = StdRng::seed_from_u64(0);
+
+    let mut origins: Vec<Vec2> = spawn_snowflakes(&mut rng, &area, amount)
+        .iter()
+        .map(|snowflake| snowflake.origin)
+        .collect();
+    let mut checksum = 0.0;
+    let legacy = measure(|index| {
+        checksum += legacy_frame(&mut origins, index as f64 * TIME_STEP as f64);
+    });
+
+    let mut batched = |amount: usize| {
+        let mut snowflakes = spawn_snowflakes(&mut rng, &area, amount);
+        let mut mesh = build_snowflake_mesh(&snowflakes, &SNOWFLAKE_LAYERS[0], 2.0, &atlas);
+        let mut vertices = 0;
+        let duration = measure(|index| {
+            simulate_snowflakes(
+                &mut snowflakes,
+                &SNOWFLAKE_LAYERS[0],
+                &noise,
+                &snowfall,
+                index as f32 * TIME_STEP,
+                &mut rng,
+            );
+            fill_snowflake_mesh(&mut mesh, &snowflakes, &SNOWFLAKE_LAYERS[0], 2.0, &atlas);
+            vertices += mesh.count_vertices();
+        });
+        (duration, vertices)
+    };
+    let (batched_1x, _) = batched(amount);
+    let (batched_10x, vertices) = batched(amount * DENSITY_FACTOR);
+
+    let budget = Duration::from_secs_f32(TIME_STEP);
+    let report = |name: &str, flakes: usize, duration: Duration| {
+        println!(
+            "{:<32} {:>6} flakes {:>10.3?}/frame {:>6.2}% of frame budget",
+            name,
+            flakes,
+            duration,
+            duration.as_secs_f64() / budget.as_secs_f64() * 100.0
+        );
+    };
+    report("legacy noise, 1x baseline", amount, legacy);
+    report("flow field + mesh, 1x baseline", amount, batched_1x);
+    report(
+        "flow field + mesh, 10x baseline",
+        amount * DENSITY_FACTOR,
+        batched_10x,
+    );
+    println!("(checksums {} / {} vertices)", checksum, vertices);
+
+    let share = batched_10x.as_secs_f64() / budget.as_secs_f64();
+    assert!(
+        share <= MAX_BUDGET_SHARE,
+        "10x baseline density takes {:.1}% of a frame, expected at most {:.1}%",
+        share * 100.0,
+        MAX_BUDGET_SHARE * 100.0
+    );
+}
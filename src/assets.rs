@@ -1,8 +1,13 @@
+use crate::audio::decode_audio;
+use crate::levels::LevelData;
 use bevy::asset::{Asset, AssetPath};
 use bevy::prelude::*;
 use bevy::sprite::Rect;
+use rodio::Source;
+use serde::de::DeserializeOwned;
 use std::collections::HashMap;
 use std::ops::DerefMut;
+use std::path::{Path, PathBuf};
 
 #[derive(Default)]
 pub struct AssetsLoading {
@@ -13,9 +18,87 @@ pub struct AssetsLoading {
 
 pub struct AssetsReady(pub bool);
 
+pub const VOICED_SPEECH: &[(&str, &str)] = &[
+    (
+        "arrive_1",
+        "You found the door! Press <F> when being close to enter the house!",
+    ),
+    ("enter_house_1", "You are entering the house!"),
+    ("hello_1", "Hello, I'm Santa!"),
+    ("hello_2", "Help me distribute all the presents!"),
+    ("hello_3", "And do not unwrap them yourself!"),
+    ("tutorial_1", "But first, you have to walk to the right."),
+    ("tutorial_2", "To do that, press <D> on your keyboard."),
+    ("tutorial_3", "Do it now!"),
+];
+
+pub const SILENT_SPEECH: &[(&str, &str)] = &[
+    ("spotted_1", "Uh oh, somebody is awake! Get out of sight!"),
+    (
+        "remember_warning_1",
+        "The elf said not to make noise near sleeping people. No jumping!",
+    ),
+    ("caught_1", "I've been caught! Back to the door..."),
+    (
+        "dawn_1",
+        "The sun is coming up. I have to be gone before anyone wakes!",
+    ),
+];
+
+#[derive(Clone)]
+pub struct Portrait {
+    pub atlas: Handle<TextureAtlas>,
+    pub index: u32,
+}
+
 pub struct Speech {
-    pub audio: Handle<AudioSource>,
+    pub audio: Option<Handle<AudioSource>>,
     pub text: String,
+    pub portrait: Option<Portrait>,
+    pub duration: f32,
+}
+
+fn asset_root() -> PathBuf {
+    if let Ok(manifest_dir) = std::env::var("CARGO_MANIFEST_DIR") {
+        PathBuf::from(manifest_dir)
+    } else {
+        std::env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(Path::to_owned))
+            .unwrap_or_default()
+    }
+    .join("assets")
+}
+
+pub fn load_data<T: DeserializeOwned>(path: &str) -> Option<T> {
+    let full_path = asset_root().join(path);
+    let content = match std::fs::read_to_string(&full_path) {
+        Ok(content) => content,
+        Err(error) => {
+            error!("Could not read data file {:?}: {}", full_path, error);
+            return None;
+        }
+    };
+
+    match ron::de::from_str(&content) {
+        Ok(data) => Some(data),
+        Err(error) => {
+            error!("Could not parse data file {:?}: {}", full_path, error);
+            None
+        }
+    }
+}
+
+fn estimate_speech_duration(text: &str) -> f32 {
+    0.5 + text.chars().count() as f32 * 0.06
+}
+
+fn measure_speech_duration(audio_source: &AudioSource) -> Option<f32> {
+    let decoder = decode_audio(audio_source)?;
+    let channels = decoder.channels() as f32;
+    let sample_rate = decoder.sample_rate() as f32;
+    let samples = decoder.count() as f32;
+    Some(samples / channels / sample_rate)
 }
 
 #[derive(Default)]
@@ -25,12 +108,54 @@ pub struct SantaAssets {
 
     // Speech
     pub speech: HashMap<String, Speech>,
+    pub portraits: HashMap<String, Portrait>,
+
+    // Audio
+    pub tracks: HashMap<String, Handle<AudioSource>>,
+    pub sfx: HashMap<String, Handle<AudioSource>>,
 
     // Textures
     pub santa: Handle<TextureAtlas>,
     pub snowflakes: Handle<TextureAtlas>,
-    pub outside_background: Handle<TextureAtlas>,
-    pub indoors_background: Handle<TextureAtlas>,
+    pub backgrounds: HashMap<String, Handle<Texture>>,
+}
+
+pub fn santa_atlas(texture: Handle<Texture>) -> TextureAtlas {
+    let mut santa = TextureAtlas::new_empty(texture, Vec2::new(111.0, 51.0));
+    santa.add_texture(Rect {
+        min: Vec2::new(0.0, 0.0),
+        max: Vec2::new(36.0, 51.0),
+    });
+    santa.add_texture(Rect {
+        min: Vec2::new(36.0, 0.0),
+        max: Vec2::new(75.0, 51.0),
+    });
+    santa.add_texture(Rect {
+        min: Vec2::new(75.0, 0.0),
+        max: Vec2::new(111.0, 51.0),
+    });
+    santa
+}
+
+pub fn snowflake_atlas(texture: Handle<Texture>) -> TextureAtlas {
+    let mut snowflakes = TextureAtlas::new_empty(texture, Vec2::new(27.0, 13.0));
+    snowflakes.add_texture(Rect {
+        min: Vec2::new(0.0, 0.0),
+        max: Vec2::new(13.0, 13.0),
+    });
+    snowflakes.add_texture(Rect {
+        min: Vec2::new(13.0, 0.0),
+        max: Vec2::new(20.0, 7.0),
+    });
+    snowflakes.add_texture(Rect {
+        min: Vec2::new(20.0, 0.0),
+        max: Vec2::new(27.0, 7.0),
+    });
+    snowflakes.add_texture(Rect {
+        min: Vec2::new(13.0, 7.0),
+        max: Vec2::new(17.0, 11.0),
+    });
+    snowflakes
 }
 
 fn load_asset<'a, P: Into<AssetPath<'a>>, R: Asset>(
@@ -49,6 +174,7 @@ fn load_speech<'a, P: Into<AssetPath<'a>> + ToString>(
     assets: &mut SantaAssets,
     path: P,
     text: String,
+    portrait: Option<Portrait>,
 ) {
     let name = path.to_string();
     let name = name
@@ -60,66 +186,55 @@ fn load_speech<'a, P: Into<AssetPath<'a>> + ToString>(
         .unwrap()
         .to_owned();
 
-    let audio = load_asset(server, loading, path);
-    assets.speech.insert(name, Speech { audio, text });
+    let audio = Some(load_asset(server, loading, path));
+    let duration = estimate_speech_duration(&text);
+    assets.speech.insert(
+        name,
+        Speech {
+            audio,
+            text,
+            portrait,
+            duration,
+        },
+    );
+}
+
+pub fn add_silent_speech(
+    assets: &mut SantaAssets,
+    name: &str,
+    text: String,
+    portrait: Option<Portrait>,
+) {
+    let duration = estimate_speech_duration(&text);
+    assets.speech.insert(
+        name.to_owned(),
+        Speech {
+            audio: None,
+            text,
+            portrait,
+            duration,
+        },
+    );
 }
 
 fn load_assets_system(
     server: Res<AssetServer>,
     mut loading: ResMut<AssetsLoading>,
     mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+    level_data: Res<LevelData>,
     mut commands: Commands,
 ) {
     // Textures
     let santa = load_asset(&server, &mut loading, "texture/santa_spritesheet.png");
-    let mut santa = TextureAtlas::new_empty(santa, Vec2::new(111.0, 51.0));
-    santa.add_texture(Rect {
-        min: Vec2::new(0.0, 0.0),
-        max: Vec2::new(36.0, 51.0),
-    });
-    santa.add_texture(Rect {
-        min: Vec2::new(36.0, 0.0),
-        max: Vec2::new(75.0, 51.0),
-    });
-    santa.add_texture(Rect {
-        min: Vec2::new(75.0, 0.0),
-        max: Vec2::new(111.0, 51.0),
-    });
-    let santa = texture_atlases.add(santa);
+    let santa = texture_atlases.add(santa_atlas(santa));
 
     let snowflake = load_asset(&server, &mut loading, "texture/snowflake_spritesheet.png");
-    let mut snowflakes = TextureAtlas::new_empty(snowflake, Vec2::new(27.0, 13.0));
-    snowflakes.add_texture(Rect {
-        min: Vec2::new(0.0, 0.0),
-        max: Vec2::new(13.0, 13.0),
-    });
-    snowflakes.add_texture(Rect {
-        min: Vec2::new(13.0, 0.0),
-        max: Vec2::new(20.0, 7.0),
-    });
-    snowflakes.add_texture(Rect {
-        min: Vec2::new(20.0, 0.0),
-        max: Vec2::new(27.0, 7.0),
-    });
-    snowflakes.add_texture(Rect {
-        min: Vec2::new(13.0, 7.0),
-        max: Vec2::new(17.0, 11.0),
-    });
-    let snowflakes = texture_atlases.add(snowflakes);
+    let snowflakes = texture_atlases.add(snowflake_atlas(snowflake));
 
-    let outside_background = load_asset(
-        &server,
-        &mut loading,
-        "texture/background_outside_spritesheet.png",
-    );
-    let outside_background =
-        TextureAtlas::from_grid(outside_background, Vec2::new(540.0, 210.0), 1, 1);
-    let outside_background = texture_atlases.add(outside_background);
-
-    let indoors_background = load_asset(&server, &mut loading, "texture/background_indoors.png");
-    let indoors_background =
-        TextureAtlas::from_grid(indoors_background, Vec2::new(540.0, 210.0), 1, 1);
-    let indoors_background = texture_atlases.add(indoors_background);
+    let santa_portrait = Some(Portrait {
+        atlas: santa.clone(),
+        index: 0,
+    });
 
     let mut assets = SantaAssets {
         // Fonts
@@ -127,72 +242,75 @@ fn load_assets_system(
 
         // Speech
         speech: Default::default(),
+        portraits: Default::default(),
+
+        // Audio
+        tracks: Default::default(),
+        sfx: Default::default(),
 
         // Textures
         santa,
         snowflakes,
-        outside_background,
-        indoors_background,
+        backgrounds: Default::default(),
     };
 
     // Speech
-    load_speech(
-        &server,
-        &mut loading,
-        &mut assets,
-        "speech/arrive_1.ogg",
-        "You found the door! Press <F> when being close to enter the house!".to_owned(),
-    );
-    load_speech(
-        &server,
-        &mut loading,
-        &mut assets,
-        "speech/enter_house_1.ogg",
-        "You are entering the house!".to_owned(),
-    );
-    load_speech(
-        &server,
-        &mut loading,
-        &mut assets,
-        "speech/hello_1.ogg",
-        "Hello, I'm Santa!".to_owned(),
-    );
-    load_speech(
-        &server,
-        &mut loading,
-        &mut assets,
-        "speech/hello_2.ogg",
-        "Help me distribute all the presents!".to_owned(),
-    );
-    load_speech(
-        &server,
-        &mut loading,
-        &mut assets,
-        "speech/hello_3.ogg",
-        "And do not unwrap them yourself!".to_owned(),
-    );
-    load_speech(
-        &server,
-        &mut loading,
-        &mut assets,
-        "speech/tutorial_1.ogg",
-        "But first, you have to walk to the right.".to_owned(),
-    );
-    load_speech(
-        &server,
-        &mut loading,
-        &mut assets,
-        "speech/tutorial_2.ogg",
-        "To do that, press <D> on your keyboard.".to_owned(),
-    );
-    load_speech(
-        &server,
-        &mut loading,
-        &mut assets,
-        "speech/tutorial_3.ogg",
-        "Do it now!".to_owned(),
+    assets
+        .portraits
+        .insert("santa".to_owned(), santa_portrait.clone().unwrap());
+    assets.portraits.insert(
+        "elf".to_owned(),
+        Portrait {
+            atlas: assets.santa.clone(),
+            index: 2,
+        },
     );
 
+    for (name, text) in VOICED_SPEECH {
+        let path = format!("speech/{}.ogg", name);
+        load_speech(
+            &server,
+            &mut loading,
+            &mut assets,
+            path.as_str(),
+            text.to_string(),
+            santa_portrait.clone(),
+        );
+    }
+    for (name, text) in SILENT_SPEECH {
+        add_silent_speech(&mut assets, name, text.to_string(), santa_portrait.clone());
+    }
+
+    // Audio
+    for level in level_data.levels.values() {
+        for path in level.music.iter().chain(level.ambience.iter()) {
+            if !assets.tracks.contains_key(path) {
+                let track = load_asset(&server, &mut loading, path.as_str());
+                assets.tracks.insert(path.clone(), track);
+            }
+        }
+    }
+    for path in level_data
+        .surfaces
+        .values()
+        .flat_map(|surface| surface.all())
+    {
+        if !assets.sfx.contains_key(path) {
+            let sound = load_asset(&server, &mut loading, path.as_str());
+            assets.sfx.insert(path.clone(), sound);
+        }
+    }
+
+    // Backgrounds
+    for level in level_data.levels.values() {
+        for layer in &level.background {
+            if !assets.backgrounds.contains_key(&layer.texture) {
+                let texture = load_asset(&server, &mut loading, layer.texture.as_str());
+                assets.backgrounds.insert(layer.texture.clone(), texture);
+            }
+        }
+    }
+
     commands.insert_resource(assets);
     commands.insert_resource(AssetsReady(false));
 }
@@ -201,6 +319,8 @@ fn check_assets_ready_system(
     server: Res<AssetServer>,
     mut loading: ResMut<AssetsLoading>,
     mut assets_ready: ResMut<AssetsReady>,
+    mut santa_assets: ResMut<SantaAssets>,
+    audio_sources: Res<Assets<AudioSource>>,
 ) {
     use bevy::asset::LoadState;
 
@@ -237,6 +357,17 @@ fn check_assets_ready_system(
             )
         }
 
+        for speech in santa_assets.speech.values_mut() {
+            if let Some(duration) = speech
+                .audio
+                .as_ref()
+                .and_then(|audio| audio_sources.get(audio))
+                .and_then(measure_speech_duration)
+            {
+                speech.duration = duration;
+            }
+        }
+
         assets_ready.0 = true;
     }
 }
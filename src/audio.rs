@@ -0,0 +1,389 @@
+use crate::assets::SantaAssets;
+use crate::config::{AudioSettings, UserConfig};
+use crate::console::{is_console_open, Console};
+use crate::controls::KeyBindings;
+use crate::levels::{LevelData, LevelState};
+use bevy::audio::Mp3Loader;
+use bevy::prelude::*;
+use bevy::window::WindowFocused;
+use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink, Source};
+use std::io::Cursor;
+use std::sync::Arc;
+
+const CROSSFADE_SECS: f32 = 1.5;
+const DUCK_VOLUME: f32 = 0.35;
+const DUCK_SECS: f32 = 0.3;
+const VOLUME_STEP: f32 = 0.1;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AudioChannel {
+    Music,
+    Ambience,
+    Voice,
+    Sfx,
+}
+
+pub fn decode_audio(audio_source: &AudioSource) -> Option<Decoder<Cursor<Arc<[u8]>>>> {
+    match Decoder::new(Cursor::new(audio_source.bytes.clone())) {
+        Ok(decoder) => Some(decoder),
+        Err(error) => {
+            error!("Could not decode audio: {}", error);
+            None
+        }
+    }
+}
+
+struct LoopingTrack {
+    handle: Handle<AudioSource>,
+    sink: Sink,
+    fade: f32,
+}
+
+#[derive(Default)]
+struct TrackChannel {
+    current: Option<LoopingTrack>,
+    fading_out: Vec<LoopingTrack>,
+}
+
+impl TrackChannel {
+    fn update(&mut self, fade_step: f32, volume: f32) {
+        if let Some(current) = &mut self.current {
+            current.fade = (current.fade + fade_step).min(1.0);
+            current.sink.set_volume(current.fade * volume);
+        }
+
+        for track in &mut self.fading_out {
+            track.fade = (track.fade - fade_step).max(0.0);
+            track.sink.set_volume(track.fade * volume);
+        }
+        self.fading_out.retain(|track| track.fade > 0.0);
+    }
+
+    fn sinks(&self) -> impl Iterator<Item = &Sink> {
+        self.current
+            .iter()
+            .chain(self.fading_out.iter())
+            .map(|track| &track.sink)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct VoiceLine(u64);
+
+pub struct SantaAudio {
+    output: Option<(OutputStream, OutputStreamHandle)>,
+    settings: AudioSettings,
+    music: TrackChannel,
+    ambience: TrackChannel,
+    voice: Option<(VoiceLine, Sink)>,
+    next_voice_line: u64,
+    duck: f32,
+    paused: bool,
+    sfx: Vec<(Sink, f32)>,
+}
+
+impl SantaAudio {
+    fn new() -> Self {
+        let output = match OutputStream::try_default() {
+            Ok(output) => Some(output),
+            Err(error) => {
+                warn!("No audio output available: {}", error);
+                None
+            }
+        };
+        Self::with_output(output)
+    }
+
+    // Plays nothing, for running the game without an audio device.
+    pub fn silent() -> Self {
+        Self::with_output(None)
+    }
+
+    fn with_output(output: Option<(OutputStream, OutputStreamHandle)>) -> Self {
+        Self {
+            output,
+            settings: AudioSettings::default(),
+            music: TrackChannel::default(),
+            ambience: TrackChannel::default(),
+            voice: None,
+            next_voice_line: 0,
+            duck: 1.0,
+            paused: false,
+            sfx: Vec::new(),
+        }
+    }
+
+    pub fn volume(&self, channel: AudioChannel) -> f32 {
+        self.settings.master
+            * match channel {
+                AudioChannel::Music => self.settings.music,
+                AudioChannel::Ambience => self.settings.ambience,
+                AudioChannel::Voice => self.settings.voice,
+                AudioChannel::Sfx => self.settings.sfx,
+            }
+    }
+
+    fn new_sink(&self) -> Option<Sink> {
+        let (_, stream_handle) = self.output.as_ref()?;
+        match Sink::try_new(stream_handle) {
+            Ok(sink) => Some(sink),
+            Err(error) => {
+                error!("Could not create audio sink: {}", error);
+                None
+            }
+        }
+    }
+
+    fn track_channel(&self, channel: AudioChannel) -> Option<&TrackChannel> {
+        match channel {
+            AudioChannel::Music => Some(&self.music),
+            AudioChannel::Ambience => Some(&self.ambience),
+            AudioChannel::Voice | AudioChannel::Sfx => None,
+        }
+    }
+
+    fn track_channel_mut(&mut self, channel: AudioChannel) -> Option<&mut TrackChannel> {
+        match channel {
+            AudioChannel::Music => Some(&mut self.music),
+            AudioChannel::Ambience => Some(&mut self.ambience),
+            AudioChannel::Voice | AudioChannel::Sfx => None,
+        }
+    }
+
+    pub fn set_settings(&mut self, settings: AudioSettings) {
+        self.settings = settings;
+
+        if let Some((_, voice)) = &self.voice {
+            voice.set_volume(self.volume(AudioChannel::Voice));
+        }
+        let sfx_volume = self.volume(AudioChannel::Sfx);
+        for (sink, volume) in &self.sfx {
+            sink.set_volume(volume * sfx_volume);
+        }
+        // Looping tracks pick up the new volume on the next update.
+    }
+
+    pub fn play_voice(&mut self, audio_source: &AudioSource) -> Option<VoiceLine> {
+        self.stop_voice();
+
+        let decoder = decode_audio(audio_source)?;
+        let sink = self.new_sink()?;
+        sink.set_volume(self.volume(AudioChannel::Voice));
+        sink.append(decoder);
+        if self.paused {
+            sink.pause();
+        }
+
+        let voice_line = VoiceLine(self.next_voice_line);
+        self.next_voice_line += 1;
+        self.voice = Some((voice_line, sink));
+        Some(voice_line)
+    }
+
+    pub fn stop_voice(&mut self) {
+        if let Some((_, sink)) = self.voice.take() {
+            sink.stop();
+        }
+    }
+
+    pub fn stop_voice_line(&mut self, voice_line: VoiceLine) {
+        if matches!(&self.voice, Some((playing, _)) if *playing == voice_line) {
+            self.stop_voice();
+        }
+    }
+
+    pub fn is_voice_playing(&self) -> bool {
+        matches!(&self.voice, Some((_, sink)) if !sink.empty())
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        if self.paused == paused {
+            return;
+        }
+        self.paused = paused;
+
+        let voice = self.voice.iter().map(|(_, sink)| sink);
+        let sfx = self.sfx.iter().map(|(sink, _)| sink);
+        let tracks = self
+            .music
+            .sinks()
+            .chain(self.ambience.sinks())
+            .chain(voice)
+            .chain(sfx);
+        for sink in tracks {
+            if paused {
+                sink.pause();
+            } else {
+                sink.play();
+            }
+        }
+    }
+
+    pub fn play_sfx(&mut self, audio_source: &AudioSource, volume: f32, speed: f32) {
+        let decoder = match decode_audio(audio_source) {
+            Some(decoder) => decoder,
+            None => return,
+        };
+        if let Some(sink) = self.new_sink() {
+            sink.set_volume(volume * self.volume(AudioChannel::Sfx));
+            sink.append(decoder.speed(speed));
+            if self.paused {
+                sink.pause();
+            }
+            self.sfx.push((sink, volume));
+        }
+    }
+
+    pub fn set_track(
+        &mut self,
+        channel: AudioChannel,
+        track: Option<(&Handle<AudioSource>, &AudioSource)>,
+    ) {
+        let current_handle = self
+            .track_channel(channel)
+            .and_then(|track_channel| track_channel.current.as_ref())
+            .map(|current| current.handle.clone());
+        if current_handle.as_ref() == track.map(|(handle, _)| handle) {
+            return;
+        }
+
+        let new_track = track.and_then(|(handle, audio_source)| {
+            let decoder = decode_audio(audio_source)?;
+            let sink = self.new_sink()?;
+            sink.set_volume(0.0);
+            sink.append(decoder.repeat_infinite());
+            if self.paused {
+                sink.pause();
+            }
+            Some(LoopingTrack {
+                handle: handle.clone(),
+                sink,
+                fade: 0.0,
+            })
+        });
+
+        if let Some(track_channel) = self.track_channel_mut(channel) {
+            if let Some(previous) = track_channel.current.take() {
+                track_channel.fading_out.push(previous);
+            }
+            track_channel.current = new_track;
+        }
+    }
+
+    fn update(&mut self, delta: f32) {
+        if self.paused {
+            return;
+        }
+
+        // Music and ambience duck under a playing voice line.
+        let duck_target = if self.is_voice_playing() {
+            DUCK_VOLUME
+        } else {
+            1.0
+        };
+        let duck_step = delta / DUCK_SECS * (1.0 - DUCK_VOLUME);
+        self.duck = if self.duck < duck_target {
+            (self.duck + duck_step).min(duck_target)
+        } else {
+            (self.duck - duck_step).max(duck_target)
+        };
+
+        let fade_step = delta / CROSSFADE_SECS;
+        let music_volume = self.volume(AudioChannel::Music) * self.duck;
+        let ambience_volume = self.volume(AudioChannel::Ambience) * self.duck;
+        self.music.update(fade_step, music_volume);
+        self.ambience.update(fade_step, ambience_volume);
+        self.sfx.retain(|(sink, _)| !sink.empty());
+    }
+}
+
+fn apply_audio_settings_system(config: Res<UserConfig>, mut santa_audio: NonSendMut<SantaAudio>) {
+    if config.is_changed() {
+        santa_audio.set_settings(config.audio.clone());
+    }
+}
+
+fn volume_keys_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    console: Option<Res<Console>>,
+    mut config: ResMut<UserConfig>,
+) {
+    if is_console_open(console) {
+        return;
+    }
+
+    let mut master = config.audio.master;
+    if keyboard_input.just_released(key_bindings.volume_down) {
+        master -= VOLUME_STEP;
+    }
+    if keyboard_input.just_released(key_bindings.volume_up) {
+        master += VOLUME_STEP;
+    }
+
+    let master = master.max(0.0).min(1.0);
+    if (master - config.audio.master).abs() > f32::EPSILON {
+        config.audio.master = master;
+        info!("Master volume set to {:.0}%", master * 100.0);
+    }
+}
+
+fn level_tracks_system(
+    state: Res<State<LevelState>>,
+    level_data: Res<LevelData>,
+    santa_assets: Res<SantaAssets>,
+    audio_sources: Res<Assets<AudioSource>>,
+    mut santa_audio: NonSendMut<SantaAudio>,
+) {
+    let level = level_data.get(state.current());
+
+    for &(channel, path) in [
+        (AudioChannel::Music, &level.music),
+        (AudioChannel::Ambience, &level.ambience),
+    ]
+    .iter()
+    {
+        let track = path
+            .as_ref()
+            .and_then(|path| santa_assets.tracks.get(path))
+            .map(|handle| (handle, audio_sources.get(handle)));
+        match track {
+            Some((handle, Some(audio_source))) => {
+                santa_audio.set_track(channel, Some((handle, audio_source)))
+            }
+            // Still loading, try again next frame.
+            Some((_, None)) => {}
+            None => santa_audio.set_track(channel, None),
+        }
+    }
+}
+
+fn pause_audio_on_focus_system(
+    mut window_focused_events: EventReader<WindowFocused>,
+    mut santa_audio: NonSendMut<SantaAudio>,
+) {
+    if let Some(window_focused) = window_focused_events.iter().last() {
+        santa_audio.set_paused(!window_focused.focused);
+    }
+}
+
+fn update_audio_system(time: Res<Time>, mut santa_audio: NonSendMut<SantaAudio>) {
+    santa_audio.update(time.delta_seconds());
+}
+
+pub struct SantaAudioPlugin;
+
+impl Plugin for SantaAudioPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        // Bevy's `AudioPlugin` is disabled so only `SantaAudio` opens the output device, but the
+        // game still loads its clips as `AudioSource` assets.
+        app.add_asset::<AudioSource>()
+            .init_asset_loader::<Mp3Loader>()
+            .insert_non_send_resource(SantaAudio::new())
+            .add_system(apply_audio_settings_system.system())
+            .add_system(volume_keys_system.system())
+            .add_system(pause_audio_on_focus_system.system())
+            .add_system(level_tracks_system.system().label("level_tracks"))
+            .add_system(update_audio_system.system().after("level_tracks"));
+    }
+}
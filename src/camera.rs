@@ -1,14 +1,164 @@
+use crate::assets::load_data;
+use crate::dialogue::DialogueStarted;
 use crate::levels::LevelCameraBoundary;
-use crate::physics::Position;
+use crate::npc::NpcEvent;
+use crate::physics::{Landed, Position, Speed};
 use crate::player::Santa;
+use crate::rng::GameRng;
+use crate::TIME_STEP;
 use bevy::prelude::*;
 use bevy::render::camera::{
     camera_system, Camera, CameraProjection, DepthCalculation, VisibleEntities,
 };
+use rand::distributions::{Distribution, Uniform};
+use serde_derive::Deserialize;
+use std::collections::HashMap;
+
+const LOOK_AHEAD_MIN_SPEED: f32 = 1.0;
+const RNG_STREAM: &str = "camera";
+const VIRTUAL_WIDTH: f32 = 200.0;
+const VIRTUAL_HEIGHT: f32 = 150.0;
+const LETTERBOX_DEPTH: f32 = -0.5;
+const TRAUMA_DECAY: f32 = 1.5;
+const MAX_SHAKE_OFFSET: f32 = 6.0;
+const MAX_SHAKE_ANGLE: f32 = 0.05;
+const ZOOM_SPEED: f32 = 4.0;
+const CAUGHT_TRAUMA: f32 = 0.6;
+const HARD_LANDING_SPEED: f32 = 200.0;
+const MAX_LANDING_TRAUMA: f32 = 0.4;
+
+#[derive(Clone, Debug, Deserialize)]
+pub enum CameraEffect {
+    Shake(f32),
+    Zoom {
+        zoom: f32,
+        duration: f32,
+    },
+    PanTo {
+        target: Vec2,
+        duration: f32,
+        hold: f32,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct CameraSettings {
+    pub smooth_time: f32,
+    pub look_ahead: f32,
+    pub look_ahead_smooth_time: f32,
+    pub dead_zone_height: f32,
+    pub pixel_perfect: bool,
+    pub dialogue_cues: HashMap<String, Vec<CameraEffect>>,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        Self {
+            smooth_time: 0.2,
+            look_ahead: 30.0,
+            look_ahead_smooth_time: 0.6,
+            dead_zone_height: 40.0,
+            pixel_perfect: false,
+            dialogue_cues: Default::default(),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct CameraController {
+    position: Vec2,
+    velocity: Vec2,
+    look_ahead: f32,
+    look_ahead_velocity: f32,
+    focus_y: f32,
+}
+
+struct CameraPan {
+    from: Option<Vec2>,
+    target: Vec2,
+    duration: f32,
+    hold: f32,
+    elapsed: f32,
+}
+
+pub struct CameraEffects {
+    trauma: f32,
+    zoom: f32,
+    zoom_target: f32,
+    zoom_remaining: f32,
+    pan: Option<CameraPan>,
+}
+
+impl Default for CameraEffects {
+    fn default() -> Self {
+        Self {
+            trauma: 0.0,
+            zoom: 1.0,
+            zoom_target: 1.0,
+            zoom_remaining: 0.0,
+            pan: None,
+        }
+    }
+}
+
+impl CameraEffects {
+    fn advance_pan(&mut self, current: Vec2, delta: f32) -> Option<Vec2> {
+        let pan = self.pan.as_mut()?;
+        let from = *pan.from.get_or_insert(current);
+        pan.elapsed += delta;
+        let position = from.lerp(
+            pan.target,
+            ease_in_out(pan.elapsed / pan.duration.max(f32::EPSILON)),
+        );
+
+        // Once the hold is over the regular follow smoothing takes the camera back.
+        if pan.elapsed >= pan.duration + pan.hold {
+            self.pan = None;
+        }
+        Some(position)
+    }
+
+    pub fn apply(&mut self, effect: &CameraEffect) {
+        match *effect {
+            CameraEffect::Shake(trauma) => {
+                self.trauma = (self.trauma + trauma).min(1.0);
+            }
+            CameraEffect::Zoom { zoom, duration } => {
+                self.zoom_target = zoom.max(f32::EPSILON);
+                self.zoom_remaining = duration;
+            }
+            CameraEffect::PanTo {
+                target,
+                duration,
+                hold,
+            } => {
+                self.pan = Some(CameraPan {
+                    from: None,
+                    target,
+                    duration,
+                    hold,
+                    elapsed: 0.0,
+                });
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum LetterboxBar {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
 
 pub struct SantaOrthoProjection {
     pub projection_matrix: Mat4,
     pub viewport_dimensions: Rect<f32>,
+    pub canvas: Rect<f32>,
+    pub pixel_perfect: bool,
+    pub zoom: f32,
 }
 
 impl CameraProjection for SantaOrthoProjection {
@@ -20,21 +170,55 @@ impl CameraProjection for SantaOrthoProjection {
         const MIN_SCALE: f32 = 4.0;
         const BACKGROUND_HEIGHT: f32 = 200.0;
 
-        width /= MIN_SCALE;
-        height /= MIN_SCALE;
+        if self.pixel_perfect {
+            let base_scale = (width / VIRTUAL_WIDTH)
+                .min(height / VIRTUAL_HEIGHT)
+                .floor()
+                .max(1.0);
+            // Zoom in whole scale steps so a virtual pixel stays a whole number of screen pixels.
+            let scale = (base_scale * self.zoom).round().max(1.0);
+            let zoom = scale / base_scale;
 
-        if height > BACKGROUND_HEIGHT {
-            let scale_factor = BACKGROUND_HEIGHT / height;
-            width *= scale_factor;
-            height *= scale_factor;
-        }
+            // Start on a whole window pixel so virtual pixels line up with screen pixels.
+            let left = -(width / 2.0).floor() / scale;
+            let bottom = -(height / 2.0).floor() / scale;
+            self.viewport_dimensions = Rect {
+                left,
+                right: left + width / scale,
+                bottom,
+                top: bottom + height / scale,
+            };
+            self.canvas = Rect {
+                left: -VIRTUAL_WIDTH / 2.0 / zoom,
+                right: VIRTUAL_WIDTH / 2.0 / zoom,
+                bottom: -VIRTUAL_HEIGHT / 2.0 / zoom,
+                top: VIRTUAL_HEIGHT / 2.0 / zoom,
+            };
+        } else {
+            width /= MIN_SCALE;
+            height /= MIN_SCALE;
 
-        self.viewport_dimensions = Rect {
-            left: -width / 2.0,
-            right: width / 2.0,
-            bottom: -height / 2.0,
-            top: height / 2.0,
-        };
+            if height > BACKGROUND_HEIGHT {
+                let scale_factor = BACKGROUND_HEIGHT / height;
+                width *= scale_factor;
+                height *= scale_factor;
+            }
+
+            self.viewport_dimensions = Rect {
+                left: -width / 2.0,
+                right: width / 2.0,
+                bottom: -height / 2.0,
+                top: height / 2.0,
+            };
+            self.canvas = self.viewport_dimensions;
+
+            for rect in [&mut self.viewport_dimensions, &mut self.canvas].iter_mut() {
+                rect.left /= self.zoom;
+                rect.right /= self.zoom;
+                rect.bottom /= self.zoom;
+                rect.top /= self.zoom;
+            }
+        }
         self.projection_matrix = Mat4::orthographic_rh(
             self.viewport_dimensions.left,
             self.viewport_dimensions.right,
@@ -55,45 +239,349 @@ impl Default for SantaOrthoProjection {
         let mut result = Self {
             projection_matrix: Default::default(),
             viewport_dimensions: Default::default(),
+            canvas: Default::default(),
+            pixel_perfect: false,
+            zoom: 1.0,
         };
         result.update(800.0, 600.0);
         result
     }
 }
 
-fn init_camera_system(mut commands: Commands) {
-    let projection = SantaOrthoProjection::default();
+fn init_camera_system(
+    mut commands: Commands,
+    camera_settings: Res<CameraSettings>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let mut projection = SantaOrthoProjection::default();
+    projection.pixel_perfect = camera_settings.pixel_perfect;
     let cam_name = bevy::render::render_graph::base::camera::CAMERA_2D;
     let mut camera = Camera::default();
     camera.name = Some(cam_name.to_owned());
 
-    commands.spawn_bundle((
-        Transform::from_translation(Vec3::new(0.0, 0.0, 999.0)),
-        GlobalTransform::default(),
-        VisibleEntities::default(),
-        camera,
-        projection,
-    ));
+    let letterbox_material = materials.add(Color::BLACK.into());
+    commands
+        .spawn_bundle((
+            Transform::from_translation(Vec3::new(0.0, 0.0, 999.0)),
+            GlobalTransform::default(),
+            VisibleEntities::default(),
+            camera,
+            projection,
+            CameraController::default(),
+            CameraEffects::default(),
+        ))
+        .with_children(|parent| {
+            for &bar in [
+                LetterboxBar::Left,
+                LetterboxBar::Right,
+                LetterboxBar::Top,
+                LetterboxBar::Bottom,
+            ]
+            .iter()
+            {
+                parent
+                    .spawn_bundle(SpriteBundle {
+                        material: letterbox_material.clone(),
+                        sprite: Sprite::new(Vec2::ZERO),
+                        transform: Transform::from_translation(Vec3::new(
+                            0.0,
+                            0.0,
+                            LETTERBOX_DEPTH,
+                        )),
+                        ..Default::default()
+                    })
+                    .insert(bar);
+            }
+        });
+}
+
+fn refresh_projection(
+    windows: &Windows,
+    camera: &mut Camera,
+    projection: &mut SantaOrthoProjection,
+) {
+    if let Some(window) = windows.get(camera.window) {
+        projection.update(window.width(), window.height());
+        camera.projection_matrix = projection.get_projection_matrix();
+    }
+}
+
+fn pixel_perfect_setting_system(
+    windows: Res<Windows>,
+    camera_settings: Res<CameraSettings>,
+    mut camera_query: Query<(&mut Camera, &mut SantaOrthoProjection)>,
+) {
+    if !camera_settings.is_changed() {
+        return;
+    }
+
+    for (mut camera, mut projection) in camera_query.iter_mut() {
+        if projection.pixel_perfect != camera_settings.pixel_perfect {
+            projection.pixel_perfect = camera_settings.pixel_perfect;
+            refresh_projection(&windows, &mut camera, &mut projection);
+        }
+    }
+}
+
+fn update_letterbox_system(
+    camera_query: Query<&SantaOrthoProjection, (With<Camera>, Changed<SantaOrthoProjection>)>,
+    mut bar_query: Query<(&LetterboxBar, &mut Sprite, &mut Transform)>,
+) {
+    let projection = match camera_query.iter().next() {
+        Some(projection) => projection,
+        None => return,
+    };
+    let viewport = &projection.viewport_dimensions;
+    let canvas = &projection.canvas;
+
+    for (bar, mut sprite, mut transform) in bar_query.iter_mut() {
+        let area = match bar {
+            LetterboxBar::Left => Rect {
+                left: viewport.left,
+                right: canvas.left,
+                ..*viewport
+            },
+            LetterboxBar::Right => Rect {
+                left: canvas.right,
+                right: viewport.right,
+                ..*viewport
+            },
+            LetterboxBar::Top => Rect {
+                bottom: canvas.top,
+                top: viewport.top,
+                ..*viewport
+            },
+            LetterboxBar::Bottom => Rect {
+                bottom: viewport.bottom,
+                top: canvas.bottom,
+                ..*viewport
+            },
+        };
+        sprite.size = Vec2::new(
+            (area.right - area.left).max(0.0),
+            (area.top - area.bottom).max(0.0),
+        );
+        transform.translation.x = (area.left + area.right) / 2.0;
+        transform.translation.y = (area.bottom + area.top) / 2.0;
+    }
+}
+
+// Critically damped spring towards the target, see Game Programming Gems 4, chapter 1.10.
+fn smooth_damp(current: f32, target: f32, velocity: &mut f32, smooth_time: f32, delta: f32) -> f32 {
+    let omega = 2.0 / smooth_time.max(f32::EPSILON);
+    let x = omega * delta;
+    let exp = 1.0 / (1.0 + x + 0.48 * x * x + 0.235 * x * x * x);
+    let change = current - target;
+    let temp = (*velocity + omega * change) * delta;
+    *velocity = (*velocity - omega * temp) * exp;
+    target + (change + temp) * exp
+}
+
+fn clamp_to_boundary(
+    position: Vec2,
+    camera_boundary: &LevelCameraBoundary,
+    viewport_dimensions: &Rect<f32>,
+) -> Vec2 {
+    Vec2::new(
+        position
+            .x
+            .max(camera_boundary.0.left - viewport_dimensions.left)
+            .min(camera_boundary.0.right - viewport_dimensions.right),
+        position
+            .y
+            .max(camera_boundary.0.bottom - viewport_dimensions.bottom)
+            .min(camera_boundary.0.top - viewport_dimensions.top),
+    )
+}
+
+fn camera_cues_system(
+    camera_settings: Res<CameraSettings>,
+    santa_query: Query<(), With<Santa>>,
+    mut dialogue_started_events: EventReader<DialogueStarted>,
+    mut npc_events: EventReader<NpcEvent>,
+    mut landed_events: EventReader<Landed>,
+    mut camera_effects: EventWriter<CameraEffect>,
+) {
+    for dialogue_started in dialogue_started_events.iter() {
+        if let Some(cues) = camera_settings.dialogue_cues.get(&dialogue_started.0) {
+            for cue in cues {
+                camera_effects.send(cue.clone());
+            }
+        }
+    }
+
+    for npc_event in npc_events.iter() {
+        if let NpcEvent::Caught(_) = npc_event {
+            camera_effects.send(CameraEffect::Shake(CAUGHT_TRAUMA));
+        }
+    }
+
+    for landed in landed_events.iter() {
+        if santa_query.get(landed.entity).is_ok() && landed.impact_speed > HARD_LANDING_SPEED {
+            let trauma = (landed.impact_speed / HARD_LANDING_SPEED - 1.0).min(MAX_LANDING_TRAUMA);
+            camera_effects.send(CameraEffect::Shake(trauma));
+        }
+    }
+}
+
+fn camera_effects_system(
+    windows: Res<Windows>,
+    mut camera_effect_events: EventReader<CameraEffect>,
+    mut camera_query: Query<(&mut Camera, &mut SantaOrthoProjection, &mut CameraEffects)>,
+) {
+    let delta = TIME_STEP;
+    let camera_effect_events: Vec<CameraEffect> = camera_effect_events.iter().cloned().collect();
+
+    for (mut camera, mut projection, mut effects) in camera_query.iter_mut() {
+        for effect in &camera_effect_events {
+            effects.apply(effect);
+        }
+
+        effects.trauma = (effects.trauma - TRAUMA_DECAY * delta).max(0.0);
+
+        if effects.zoom_remaining > 0.0 {
+            effects.zoom_remaining -= delta;
+        } else {
+            effects.zoom_target = 1.0;
+        }
+        let zoom =
+            effects.zoom + (effects.zoom_target - effects.zoom) * (ZOOM_SPEED * delta).min(1.0);
+        effects.zoom = if (zoom - effects.zoom_target).abs() < 0.001 {
+            effects.zoom_target
+        } else {
+            zoom
+        };
+        if (projection.zoom - effects.zoom).abs() > f32::EPSILON {
+            projection.zoom = effects.zoom;
+            refresh_projection(&windows, &mut camera, &mut projection);
+        }
+    }
+}
+
+fn ease_in_out(t: f32) -> f32 {
+    let t = t.max(0.0).min(1.0);
+    t * t * (3.0 - 2.0 * t)
 }
 
 fn follow_player_camera_system(
-    mut camera_query: Query<(&mut Transform, &SantaOrthoProjection), With<Camera>>,
-    player_query: Query<&Position, With<Santa>>,
+    camera_settings: Res<CameraSettings>,
     camera_boundary: Res<LevelCameraBoundary>,
+    mut camera_query: Query<
+        (
+            &mut Transform,
+            &mut CameraController,
+            &mut CameraEffects,
+            &SantaOrthoProjection,
+        ),
+        (With<Camera>, Without<LetterboxBar>),
+    >,
+    player_query: Query<(&Position, &Speed), With<Santa>>,
+    mut game_rng: ResMut<GameRng>,
 ) {
-    for (mut camera_transform, santa_ortho_projection) in camera_query.iter_mut() {
-        for player_position in player_query.iter() {
-            camera_transform.translation.x = player_position
-                .0
-                .x
-                .max(camera_boundary.0.left - santa_ortho_projection.viewport_dimensions.left)
-                .min(camera_boundary.0.right - santa_ortho_projection.viewport_dimensions.right);
-            camera_transform.translation.y = player_position
-                .0
-                .y
-                .max(camera_boundary.0.bottom - santa_ortho_projection.viewport_dimensions.bottom)
-                .min(camera_boundary.0.top - santa_ortho_projection.viewport_dimensions.top);
+    let (player_position, player_speed) = match player_query.iter().next() {
+        Some(player) => player,
+        None => return,
+    };
+    let delta = TIME_STEP;
+    // The boundary is re-inserted whenever a level is entered.
+    let snap = camera_boundary.is_changed();
+
+    for (mut camera_transform, mut controller, mut effects, santa_ortho_projection) in
+        camera_query.iter_mut()
+    {
+        if snap {
+            *controller = CameraController {
+                position: player_position.0,
+                focus_y: player_position.0.y,
+                ..Default::default()
+            };
         }
+
+        if player_speed.0.x.abs() >= LOOK_AHEAD_MIN_SPEED {
+            let look_ahead_target = player_speed.0.x.signum() * camera_settings.look_ahead;
+            let mut look_ahead_velocity = controller.look_ahead_velocity;
+            controller.look_ahead = smooth_damp(
+                controller.look_ahead,
+                look_ahead_target,
+                &mut look_ahead_velocity,
+                camera_settings.look_ahead_smooth_time,
+                delta,
+            );
+            controller.look_ahead_velocity = look_ahead_velocity;
+        }
+
+        let half_dead_zone = camera_settings.dead_zone_height / 2.0;
+        controller.focus_y = controller
+            .focus_y
+            .max(player_position.0.y - half_dead_zone)
+            .min(player_position.0.y + half_dead_zone);
+
+        let target = clamp_to_boundary(
+            Vec2::new(
+                player_position.0.x + controller.look_ahead,
+                controller.focus_y,
+            ),
+            &camera_boundary,
+            &santa_ortho_projection.canvas,
+        );
+
+        if snap {
+            effects.pan = None;
+        }
+        let panned = effects.advance_pan(controller.position, delta);
+
+        let position = if snap {
+            target
+        } else if let Some(position) = panned {
+            controller.velocity = Vec2::ZERO;
+            clamp_to_boundary(position, &camera_boundary, &santa_ortho_projection.canvas)
+        } else {
+            let mut velocity = controller.velocity;
+            let position = Vec2::new(
+                smooth_damp(
+                    controller.position.x,
+                    target.x,
+                    &mut velocity.x,
+                    camera_settings.smooth_time,
+                    delta,
+                ),
+                smooth_damp(
+                    controller.position.y,
+                    target.y,
+                    &mut velocity.y,
+                    camera_settings.smooth_time,
+                    delta,
+                ),
+            );
+            controller.velocity = velocity;
+            clamp_to_boundary(position, &camera_boundary, &santa_ortho_projection.canvas)
+        };
+        controller.position = position;
+
+        let shake = effects.trauma * effects.trauma;
+        let position = if shake > 0.0 {
+            let rng = game_rng.stream(RNG_STREAM);
+            let noise = Uniform::new_inclusive(-1.0, 1.0);
+            let angle = shake * MAX_SHAKE_ANGLE * noise.sample(rng);
+            // Rotating would tilt the canvas off the pixel grid, so pixel-perfect shakes only move.
+            camera_transform.rotation = if santa_ortho_projection.pixel_perfect {
+                Quat::IDENTITY
+            } else {
+                Quat::from_rotation_z(angle)
+            };
+            position + Vec2::new(noise.sample(rng), noise.sample(rng)) * shake * MAX_SHAKE_OFFSET
+        } else {
+            camera_transform.rotation = Quat::IDENTITY;
+            position
+        };
+
+        let position = if santa_ortho_projection.pixel_perfect {
+            position.round()
+        } else {
+            position
+        };
+        camera_transform.translation.x = position.x;
+        camera_transform.translation.y = position.y;
     }
 }
 
@@ -101,7 +589,9 @@ pub struct SantaCameraPlugin;
 
 impl Plugin for SantaCameraPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_startup_system(init_camera_system.system())
+        app.insert_resource(load_data::<CameraSettings>("camera.ron").unwrap_or_default())
+            .add_event::<CameraEffect>()
+            .add_startup_system(init_camera_system.system())
             .add_system(
                 camera_system::<SantaOrthoProjection>
                     .system()
@@ -113,6 +603,32 @@ impl Plugin for SantaCameraPlugin {
                     .system()
                     .label("follow_player_camera")
                     .after("camera_system"),
+            )
+            .add_system(
+                pixel_perfect_setting_system
+                    .system()
+                    .label("pixel_perfect_setting")
+                    .before("camera_system"),
+            )
+            .add_system(
+                camera_cues_system
+                    .system()
+                    .label("camera_cues")
+                    .after("dialogue_execution")
+                    .after("level_boundary"),
+            )
+            .add_system(
+                camera_effects_system
+                    .system()
+                    .label("camera_effects")
+                    .after("camera_cues")
+                    .before("camera_system"),
+            )
+            .add_system(
+                update_letterbox_system
+                    .system()
+                    .label("update_letterbox")
+                    .after("camera_system"),
             );
     }
 }
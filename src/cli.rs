@@ -0,0 +1,210 @@
+use crate::levels::LevelState;
+use bevy::prelude::*;
+use log::LevelFilter;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+pub const USAGE: &str = "Usage: santa-game [OPTIONS]
+
+Options:
+  --level LEVEL      Start in the outside or indoors level
+  --spawn X,Y        Start Santa at this position
+  --seed SEED        Seed for all random decisions
+  --log-level LEVEL  One of off, error, warn, info, debug or trace
+  --skip-intro       Skip the hello and tutorial dialogue
+  --windowed WxH     Open a window of this size
+  --record FILE      Record the input to FILE
+  --replay FILE      Replay the input recorded in FILE
+  --help             Print this message";
+
+#[derive(Clone, Debug)]
+pub struct CliArgs {
+    pub level: Option<LevelState>,
+    pub spawn: Option<Vec2>,
+    pub seed: Option<u64>,
+    pub log_level: LevelFilter,
+    pub skip_intro: bool,
+    pub windowed: Option<(f32, f32)>,
+    pub record: Option<PathBuf>,
+    pub replay: Option<PathBuf>,
+    pub help: bool,
+}
+
+impl Default for CliArgs {
+    fn default() -> Self {
+        Self {
+            level: None,
+            spawn: None,
+            seed: None,
+            log_level: LevelFilter::Info,
+            skip_intro: false,
+            windowed: None,
+            record: None,
+            replay: None,
+            help: false,
+        }
+    }
+}
+
+fn parse_pair(value: &str, separator: char) -> Option<(f32, f32)> {
+    let mut parts = value.splitn(2, separator);
+    let first = parts.next()?.trim().parse().ok()?;
+    let second = parts.next()?.trim().parse().ok()?;
+    Some((first, second))
+}
+
+fn next_value(
+    name: &str,
+    inline_value: Option<String>,
+    args: &mut impl Iterator<Item = String>,
+) -> Result<String, String> {
+    inline_value
+        .or_else(|| args.next())
+        .ok_or_else(|| format!("Missing value for {}", name))
+}
+
+impl CliArgs {
+    // Parses the arguments after the program name, as `--name value` or `--name=value`.
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut cli_args = Self::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let (name, inline_value) = match arg.find('=') {
+                Some(index) => (&arg[..index], Some(arg[index + 1..].to_owned())),
+                None => (arg.as_str(), None),
+            };
+
+            match name {
+                "--level" => {
+                    let value = next_value(name, inline_value, &mut args)?;
+                    cli_args.level = Some(
+                        LevelState::from_key(&value)
+                            .ok_or_else(|| format!("Unknown level {}", value))?,
+                    );
+                }
+                "--spawn" => {
+                    let value = next_value(name, inline_value, &mut args)?;
+                    let (x, y) = parse_pair(&value, ',')
+                        .ok_or_else(|| format!("Invalid --spawn {}, expected X,Y", value))?;
+                    cli_args.spawn = Some(Vec2::new(x, y));
+                }
+                "--seed" => {
+                    let value = next_value(name, inline_value, &mut args)?;
+                    cli_args.seed = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("Invalid --seed {}", value))?,
+                    );
+                }
+                "--log-level" => {
+                    let value = next_value(name, inline_value, &mut args)?;
+                    cli_args.log_level = LevelFilter::from_str(&value)
+                        .map_err(|_| format!("Invalid --log-level {}", value))?;
+                }
+                "--skip-intro" => cli_args.skip_intro = true,
+                "--windowed" => {
+                    let value = next_value(name, inline_value, &mut args)?;
+                    cli_args.windowed =
+                        Some(parse_pair(&value, 'x').ok_or_else(|| {
+                            format!("Invalid --windowed {}, expected WxH", value)
+                        })?);
+                }
+                "--record" => {
+                    cli_args.record = Some(next_value(name, inline_value, &mut args)?.into());
+                }
+                "--replay" => {
+                    cli_args.replay = Some(next_value(name, inline_value, &mut args)?.into());
+                }
+                "--help" | "-h" => cli_args.help = true,
+                _ => return Err(format!("Unknown argument {}", arg)),
+            }
+        }
+
+        Ok(cli_args)
+    }
+
+    pub fn from_env() -> Result<Self, String> {
+        Self::parse(std::env::args().skip(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<CliArgs, String> {
+        CliArgs::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn no_arguments_give_the_defaults() {
+        let cli_args = parse(&[]).unwrap();
+        assert_eq!(cli_args.level, None);
+        assert_eq!(cli_args.spawn, None);
+        assert_eq!(cli_args.log_level, LevelFilter::Info);
+        assert!(!cli_args.skip_intro);
+        assert!(!cli_args.help);
+    }
+
+    #[test]
+    fn values_can_follow_as_next_argument_or_after_equals() {
+        let separate = parse(&["--level", "indoors", "--seed", "42"]).unwrap();
+        let inline = parse(&["--level=indoors", "--seed=42"]).unwrap();
+        for cli_args in [separate, inline].iter() {
+            assert_eq!(cli_args.level, Some(LevelState::Indoors));
+            assert_eq!(cli_args.seed, Some(42));
+        }
+    }
+
+    #[test]
+    fn pairs_are_parsed() {
+        let cli_args = parse(&["--spawn", "150, -80", "--windowed=640x360"]).unwrap();
+        assert_eq!(cli_args.spawn, Some(Vec2::new(150.0, -80.0)));
+        assert_eq!(cli_args.windowed, Some((640.0, 360.0)));
+    }
+
+    #[test]
+    fn bad_pairs_are_rejected() {
+        assert!(parse(&["--spawn", "150"]).is_err());
+        assert!(parse(&["--spawn=150,x"]).is_err());
+        assert!(parse(&["--windowed", "640,360"]).is_err());
+    }
+
+    #[test]
+    fn flags_and_paths_are_parsed() {
+        let cli_args = parse(&[
+            "--skip-intro",
+            "--record",
+            "run.ron",
+            "--replay=bug.ron",
+            "--log-level",
+            "debug",
+            "-h",
+        ])
+        .unwrap();
+        assert!(cli_args.skip_intro);
+        assert_eq!(cli_args.record, Some(PathBuf::from("run.ron")));
+        assert_eq!(cli_args.replay, Some(PathBuf::from("bug.ron")));
+        assert_eq!(cli_args.log_level, LevelFilter::Debug);
+        assert!(cli_args.help);
+    }
+
+    #[test]
+    fn invalid_arguments_are_rejected() {
+        assert!(parse(&["--level", "attic"]).is_err());
+        assert!(parse(&["--seed", "-1"]).is_err());
+        assert!(parse(&["--log-level=loud"]).is_err());
+        assert!(parse(&["--unknown"]).is_err());
+        assert!(parse(&["indoors"]).is_err());
+    }
+
+    #[test]
+    fn missing_values_are_rejected() {
+        assert_eq!(
+            parse(&["--level"]).unwrap_err(),
+            "Missing value for --level"
+        );
+        assert!(parse(&["--record"]).is_err());
+    }
+}
@@ -0,0 +1,117 @@
+use bevy::prelude::*;
+use ron::ser::PrettyConfig;
+use serde_derive::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+const CONFIG_FILE: &str = "config.ron";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioSettings {
+    pub master: f32,
+    pub music: f32,
+    pub ambience: f32,
+    pub voice: f32,
+    pub sfx: f32,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            master: 1.0,
+            music: 0.6,
+            ambience: 0.8,
+            voice: 1.0,
+            sfx: 0.8,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DialogueConfig {
+    // Advance to the next line once the voice clip has finished.
+    pub auto_advance: bool,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UserConfig {
+    pub audio: AudioSettings,
+    pub dialogue: DialogueConfig,
+    pub seed: Option<u64>,
+}
+
+pub fn user_dir() -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share"))
+            })
+    };
+    base.map(|base| base.join("santa-game"))
+}
+
+fn load_user_config() -> UserConfig {
+    let path = match user_dir() {
+        Some(user_dir) => user_dir.join(CONFIG_FILE),
+        None => return UserConfig::default(),
+    };
+
+    match std::fs::read_to_string(&path) {
+        Ok(content) => match ron::de::from_str(&content) {
+            Ok(config) => {
+                info!("Loaded user config from {:?}", path);
+                config
+            }
+            Err(error) => {
+                error!("Could not parse user config {:?}: {}", path, error);
+                UserConfig::default()
+            }
+        },
+        Err(_) => UserConfig::default(),
+    }
+}
+
+fn save_user_config(config: &UserConfig) {
+    let user_dir = match user_dir() {
+        Some(user_dir) => user_dir,
+        None => {
+            warn!("No user directory, not saving the user config");
+            return;
+        }
+    };
+
+    let content = match ron::ser::to_string_pretty(config, PrettyConfig::new()) {
+        Ok(content) => content,
+        Err(error) => {
+            error!("Could not serialize user config: {}", error);
+            return;
+        }
+    };
+
+    let path = user_dir.join(CONFIG_FILE);
+    if let Err(error) =
+        std::fs::create_dir_all(&user_dir).and_then(|_| std::fs::write(&path, content))
+    {
+        error!("Could not save user config to {:?}: {}", path, error);
+    }
+}
+
+fn save_user_config_system(config: Res<UserConfig>) {
+    if config.is_changed() && !config.is_added() {
+        save_user_config(&config);
+    }
+}
+
+pub struct SantaConfigPlugin;
+
+impl Plugin for SantaConfigPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(load_user_config())
+            .add_system_to_stage(CoreStage::Last, save_user_config_system.system());
+    }
+}
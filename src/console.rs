@@ -0,0 +1,243 @@
+use crate::assets::SantaAssets;
+use crate::controls::{ActionState, KeyBindings};
+use crate::replay::InputRecorder;
+use bevy::prelude::*;
+use bevy::window::ReceivedCharacter;
+use std::collections::{BTreeMap, VecDeque};
+
+const LOG_LINES: usize = 10;
+const CONSOLE_FONT_SIZE: f32 = 16.0;
+
+// Runs a command with its arguments, returning the line to print.
+pub type ConsoleCommandFn = fn(&mut World, &[&str]) -> Result<String, String>;
+
+struct ConsoleCommand {
+    usage: &'static str,
+    run: ConsoleCommandFn,
+}
+
+#[derive(Default)]
+pub struct ConsoleCommands(BTreeMap<&'static str, ConsoleCommand>);
+
+pub trait ConsoleAppExt {
+    fn add_console_command(
+        &mut self,
+        name: &'static str,
+        usage: &'static str,
+        run: ConsoleCommandFn,
+    ) -> &mut Self;
+}
+
+impl ConsoleAppExt for AppBuilder {
+    fn add_console_command(
+        &mut self,
+        name: &'static str,
+        usage: &'static str,
+        run: ConsoleCommandFn,
+    ) -> &mut Self {
+        self.world_mut()
+            .get_resource_or_insert_with(ConsoleCommands::default)
+            .0
+            .insert(name, ConsoleCommand { usage, run });
+        self
+    }
+}
+
+#[derive(Default)]
+pub struct Console {
+    pub open: bool,
+    input: String,
+    log: VecDeque<String>,
+    submitted: Vec<String>,
+}
+
+impl Console {
+    pub fn print(&mut self, line: impl Into<String>) {
+        self.log.push_back(line.into());
+        while self.log.len() > LOG_LINES {
+            self.log.pop_front();
+        }
+    }
+
+    pub fn submit(&mut self, line: impl Into<String>) {
+        self.submitted.push(line.into());
+    }
+}
+
+// Systems reading raw keys are not reached by `block_actions_system` and check this instead.
+pub fn is_console_open(console: Option<Res<Console>>) -> bool {
+    console.map_or(false, |console| console.open)
+}
+
+pub fn parse_arg<T: std::str::FromStr>(
+    args: &[&str],
+    index: usize,
+    name: &str,
+) -> Result<T, String> {
+    let arg = args.get(index).ok_or_else(|| format!("Missing {}", name))?;
+    arg.parse().map_err(|_| format!("Invalid {} {}", name, arg))
+}
+
+struct ConsolePanel;
+
+fn help_command(world: &mut World, _args: &[&str]) -> Result<String, String> {
+    let commands = world.get_resource::<ConsoleCommands>().unwrap();
+    Ok(commands
+        .0
+        .values()
+        .map(|command| command.usage)
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+fn console_input_system(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    santa_assets: Res<SantaAssets>,
+    mut console: ResMut<Console>,
+    mut received_characters: EventReader<ReceivedCharacter>,
+    panel_query: Query<Entity, With<ConsolePanel>>,
+) {
+    if keyboard_input.just_pressed(key_bindings.console) {
+        // Drop the character of the toggle key itself.
+        received_characters.iter().for_each(drop);
+        console.open = !console.open;
+
+        if console.open {
+            commands
+                .spawn_bundle(TextBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        position: Rect {
+                            left: Val::Px(8.0),
+                            bottom: Val::Px(8.0),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    text: Text::with_section(
+                        "",
+                        TextStyle {
+                            font: santa_assets.font.clone(),
+                            font_size: CONSOLE_FONT_SIZE,
+                            color: Color::WHITE,
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(ConsolePanel);
+        } else {
+            for panel in panel_query.iter() {
+                commands.entity(panel).despawn_recursive();
+            }
+        }
+        return;
+    }
+
+    if !console.open {
+        return;
+    }
+
+    for received_character in received_characters.iter() {
+        if !received_character.char.is_control() {
+            console.input.push(received_character.char);
+        }
+    }
+    if keyboard_input.just_pressed(KeyCode::Back) {
+        console.input.pop();
+    }
+    if keyboard_input.just_pressed(KeyCode::Return) {
+        let line = std::mem::take(&mut console.input);
+        if !line.trim().is_empty() {
+            console.print(format!("> {}", line));
+            console.submit(line);
+        }
+    }
+}
+
+// Keeps the keys typed into the console from also moving Santa.
+fn block_actions_system(console: Res<Console>, mut action_state: ResMut<ActionState>) {
+    if console.open {
+        action_state.replace(0);
+    }
+}
+
+fn run_console_commands_system(world: &mut World) {
+    let submitted = std::mem::take(&mut world.get_resource_mut::<Console>().unwrap().submitted);
+
+    for line in submitted {
+        let mut words = line.split_whitespace();
+        let name = match words.next() {
+            Some(name) => name,
+            None => continue,
+        };
+        let args = words.collect::<Vec<_>>();
+
+        let run = world
+            .get_resource::<ConsoleCommands>()
+            .and_then(|commands| commands.0.get(name))
+            .map(|command| command.run);
+        let output = match run {
+            Some(run) => run(world, &args).unwrap_or_else(|error| format!("Error: {}", error)),
+            None => format!("Unknown command {}, try help", name),
+        };
+
+        info!("Console: {} -> {}", line, output);
+        if let Some(mut recorder) = world.get_resource_mut::<InputRecorder>() {
+            recorder.record_command(&line);
+        }
+        let mut console = world.get_resource_mut::<Console>().unwrap();
+        for output_line in output.lines() {
+            console.print(output_line);
+        }
+    }
+}
+
+fn update_console_panel_system(
+    console: Res<Console>,
+    mut panel_query: Query<&mut Text, With<ConsolePanel>>,
+) {
+    if !console.is_changed() {
+        return;
+    }
+    for mut text in panel_query.iter_mut() {
+        let mut lines = console.log.iter().cloned().collect::<Vec<_>>();
+        lines.push(format!("> {}_", console.input));
+        text.sections[0].value = lines.join("\n");
+    }
+}
+
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Console>()
+            .add_console_command("help", "help - list the commands", help_command)
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                console_input_system
+                    .system()
+                    .label("console_input")
+                    .before("update_actions"),
+            )
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                block_actions_system
+                    .system()
+                    .label("block_actions")
+                    .after("update_actions")
+                    .before("playback"),
+            )
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                run_console_commands_system.exclusive_system().at_end(),
+            )
+            .add_system(
+                update_console_panel_system
+                    .system()
+                    .label("update_console_panel"),
+            );
+    }
+}
@@ -0,0 +1,183 @@
+use bevy::input::InputSystem;
+use bevy::prelude::*;
+
+pub struct KeyBindings {
+    pub left: Vec<KeyCode>,
+    pub right: Vec<KeyCode>,
+    pub jump: Vec<KeyCode>,
+    pub action: KeyCode,
+    pub next_dialogue: KeyCode,
+    pub skip_dialogue: KeyCode,
+    pub replay_dialogue: KeyCode,
+    pub dialogue_history: KeyCode,
+    pub auto_advance: KeyCode,
+    pub volume_down: KeyCode,
+    pub volume_up: KeyCode,
+    pub menu_up: Vec<KeyCode>,
+    pub menu_down: Vec<KeyCode>,
+    pub menu_confirm: Vec<KeyCode>,
+    pub debug_overlay: KeyCode,
+    pub console: KeyCode,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            left: vec![KeyCode::A, KeyCode::Left],
+            right: vec![KeyCode::D, KeyCode::Right],
+            jump: vec![KeyCode::W, KeyCode::Up, KeyCode::Space],
+            action: KeyCode::F,
+            next_dialogue: KeyCode::P,
+            skip_dialogue: KeyCode::X,
+            replay_dialogue: KeyCode::R,
+            dialogue_history: KeyCode::H,
+            auto_advance: KeyCode::T,
+            volume_down: KeyCode::Minus,
+            volume_up: KeyCode::Equals,
+            menu_up: vec![KeyCode::W, KeyCode::Up],
+            menu_down: vec![KeyCode::S, KeyCode::Down],
+            menu_confirm: vec![KeyCode::Return, KeyCode::Space],
+            debug_overlay: KeyCode::F3,
+            console: KeyCode::Grave,
+        }
+    }
+}
+
+impl KeyBindings {
+    pub fn any_pressed(keyboard_input: &Input<KeyCode>, keys: &[KeyCode]) -> bool {
+        keys.iter().any(|key| keyboard_input.pressed(*key))
+    }
+
+    pub fn keys(&self, action: Action) -> &[KeyCode] {
+        match action {
+            Action::Left => &self.left,
+            Action::Right => &self.right,
+            Action::Jump => &self.jump,
+            Action::Interact => std::slice::from_ref(&self.action),
+            Action::NextDialogue => std::slice::from_ref(&self.next_dialogue),
+            Action::SkipDialogue => std::slice::from_ref(&self.skip_dialogue),
+            Action::ReplayDialogue => std::slice::from_ref(&self.replay_dialogue),
+            Action::DialogueHistory => std::slice::from_ref(&self.dialogue_history),
+            Action::MenuUp => &self.menu_up,
+            Action::MenuDown => &self.menu_down,
+            Action::MenuConfirm => &self.menu_confirm,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Action {
+    Left,
+    Right,
+    Jump,
+    Interact,
+    NextDialogue,
+    SkipDialogue,
+    ReplayDialogue,
+    DialogueHistory,
+    MenuUp,
+    MenuDown,
+    MenuConfirm,
+}
+
+impl Action {
+    pub const ALL: [Action; 11] = [
+        Action::Left,
+        Action::Right,
+        Action::Jump,
+        Action::Interact,
+        Action::NextDialogue,
+        Action::SkipDialogue,
+        Action::ReplayDialogue,
+        Action::DialogueHistory,
+        Action::MenuUp,
+        Action::MenuDown,
+        Action::MenuConfirm,
+    ];
+
+    fn bit(self) -> u32 {
+        1 << self as u32
+    }
+
+    fn gamepad_buttons(self) -> &'static [GamepadButtonType] {
+        match self {
+            Action::MenuUp => &[GamepadButtonType::DPadUp],
+            Action::MenuDown => &[GamepadButtonType::DPadDown],
+            Action::MenuConfirm => &[GamepadButtonType::South],
+            _ => &[],
+        }
+    }
+}
+
+// The gameplay input of one frame, kept as bits so it can be recorded and replayed.
+#[derive(Default)]
+pub struct ActionState {
+    current: u32,
+    previous: u32,
+}
+
+impl ActionState {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.current & action.bit() != 0
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.pressed(action) && self.previous & action.bit() == 0
+    }
+
+    pub fn just_released(&self, action: Action) -> bool {
+        !self.pressed(action) && self.previous & action.bit() != 0
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.current
+    }
+
+    // Overrides this frame's input, keeping the previous frame for the just pressed/released state.
+    pub fn replace(&mut self, bits: u32) {
+        self.current = bits;
+    }
+
+    fn update(&mut self, bits: u32) {
+        self.previous = self.current;
+        self.current = bits;
+    }
+}
+
+fn update_action_state_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepad_input: Res<Input<GamepadButton>>,
+    key_bindings: Res<KeyBindings>,
+    mut action_state: ResMut<ActionState>,
+) {
+    let bits = Action::ALL
+        .iter()
+        .filter(|action| {
+            KeyBindings::any_pressed(&keyboard_input, key_bindings.keys(**action))
+                || gamepad_input
+                    .get_pressed()
+                    .any(|button| action.gamepad_buttons().contains(&button.1))
+        })
+        .fold(0, |bits, action| bits | action.bit());
+    action_state.update(bits);
+}
+
+pub fn key_name(key: KeyCode) -> String {
+    format!("{:?}", key)
+}
+
+pub struct SantaControlsPlugin;
+
+impl Plugin for SantaControlsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<KeyBindings>()
+            .init_resource::<ActionState>()
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                update_action_state_system
+                    .system()
+                    .label("update_actions")
+                    .after(InputSystem),
+            );
+    }
+}
@@ -0,0 +1,281 @@
+use crate::assets::SantaAssets;
+use crate::console::{is_console_open, Console};
+use crate::controls::KeyBindings;
+use crate::dialogue::{DialogueState, ARRIVE_TRIGGER_X};
+use crate::interaction::Interactable;
+use crate::levels::{LevelCameraBoundary, LevelPlayerBoundary, LevelState, SpawnPoint};
+use crate::lighting::Unlit;
+use crate::physics::{GroundState, Position, Speed, SpriteBoundary};
+use crate::player::Santa;
+use bevy::diagnostic::{Diagnostics, FrameTimeDiagnosticsPlugin};
+use bevy::prelude::*;
+
+const OVERLAY_DEPTH: f32 = 100.0;
+const LINE_WIDTH: f32 = 1.0;
+const MARKER_SIZE: f32 = 4.0;
+const PANEL_FONT_SIZE: f32 = 16.0;
+
+#[derive(Default)]
+pub struct DebugOverlay {
+    pub visible: bool,
+}
+
+struct DebugShape;
+
+struct DebugPanel;
+
+struct DebugMaterials {
+    player_boundary: Handle<ColorMaterial>,
+    camera_boundary: Handle<ColorMaterial>,
+    sprite_boundary: Handle<ColorMaterial>,
+    trigger: Handle<ColorMaterial>,
+    spawn_point: Handle<ColorMaterial>,
+}
+
+impl FromWorld for DebugMaterials {
+    fn from_world(world: &mut World) -> Self {
+        let mut materials = world.get_resource_mut::<Assets<ColorMaterial>>().unwrap();
+        Self {
+            player_boundary: materials.add(Color::GREEN.into()),
+            camera_boundary: materials.add(Color::BLUE.into()),
+            sprite_boundary: materials.add(Color::YELLOW.into()),
+            trigger: materials.add(Color::FUCHSIA.into()),
+            spawn_point: materials.add(Color::RED.into()),
+        }
+    }
+}
+
+fn spawn_line(commands: &mut Commands, center: Vec2, size: Vec2, material: &Handle<ColorMaterial>) {
+    commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite::new(size),
+            material: material.clone(),
+            transform: Transform::from_translation(center.extend(OVERLAY_DEPTH)),
+            ..Default::default()
+        })
+        .insert(DebugShape)
+        .insert(Unlit);
+}
+
+fn spawn_outline(commands: &mut Commands, rect: &Rect<f32>, material: &Handle<ColorMaterial>) {
+    let center = Vec2::new(
+        (rect.left + rect.right) / 2.0,
+        (rect.bottom + rect.top) / 2.0,
+    );
+    let width = rect.right - rect.left + LINE_WIDTH;
+    let height = rect.top - rect.bottom + LINE_WIDTH;
+
+    for y in [rect.bottom, rect.top].iter() {
+        spawn_line(
+            commands,
+            Vec2::new(center.x, *y),
+            Vec2::new(width, LINE_WIDTH),
+            material,
+        );
+    }
+    for x in [rect.left, rect.right].iter() {
+        spawn_line(
+            commands,
+            Vec2::new(*x, center.y),
+            Vec2::new(LINE_WIDTH, height),
+            material,
+        );
+    }
+}
+
+fn offset_rect(rect: &Rect<f32>, offset: Vec2) -> Rect<f32> {
+    Rect {
+        left: rect.left + offset.x,
+        right: rect.right + offset.x,
+        top: rect.top + offset.y,
+        bottom: rect.bottom + offset.y,
+    }
+}
+
+fn toggle_debug_overlay_system(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    santa_assets: Res<SantaAssets>,
+    console: Option<Res<Console>>,
+    mut debug_overlay: ResMut<DebugOverlay>,
+    panel_query: Query<Entity, With<DebugPanel>>,
+) {
+    if is_console_open(console) || !keyboard_input.just_released(key_bindings.debug_overlay) {
+        return;
+    }
+
+    debug_overlay.visible = !debug_overlay.visible;
+    if !debug_overlay.visible {
+        for panel in panel_query.iter() {
+            commands.entity(panel).despawn_recursive();
+        }
+        return;
+    }
+
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(8.0),
+                    top: Val::Px(8.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: santa_assets.font.clone(),
+                    font_size: PANEL_FONT_SIZE,
+                    color: Color::WHITE,
+                },
+                Default::default(),
+            ),
+            ..Default::default()
+        })
+        .insert(DebugPanel);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn draw_debug_shapes_system(
+    mut commands: Commands,
+    debug_overlay: Res<DebugOverlay>,
+    debug_materials: Res<DebugMaterials>,
+    state: Res<State<LevelState>>,
+    player_boundary: Option<Res<LevelPlayerBoundary>>,
+    camera_boundary: Option<Res<LevelCameraBoundary>>,
+    spawn_point: Option<Res<SpawnPoint>>,
+    sprite_boundary_query: Query<(&Position, &SpriteBoundary)>,
+    interactable_query: Query<(&Position, &Interactable)>,
+    shape_query: Query<Entity, With<DebugShape>>,
+) {
+    for shape in shape_query.iter() {
+        commands.entity(shape).despawn();
+    }
+    if !debug_overlay.visible {
+        return;
+    }
+
+    if let Some(player_boundary) = player_boundary {
+        spawn_outline(
+            &mut commands,
+            &player_boundary.0,
+            &debug_materials.player_boundary,
+        );
+    }
+    if let Some(camera_boundary) = &camera_boundary {
+        spawn_outline(
+            &mut commands,
+            &camera_boundary.0,
+            &debug_materials.camera_boundary,
+        );
+    }
+
+    for (position, sprite_boundary) in sprite_boundary_query.iter() {
+        spawn_outline(
+            &mut commands,
+            &offset_rect(&sprite_boundary.0, position.0),
+            &debug_materials.sprite_boundary,
+        );
+    }
+
+    for (position, interactable) in interactable_query.iter() {
+        let radius = interactable.radius;
+        let area = Rect {
+            left: -radius,
+            right: radius,
+            top: radius,
+            bottom: -radius,
+        };
+        spawn_outline(
+            &mut commands,
+            &offset_rect(&area, position.0),
+            &debug_materials.trigger,
+        );
+    }
+
+    // The arrive dialogue starts once Santa walks past this line outside.
+    if let (LevelState::Outside, Some(camera_boundary)) = (state.current(), &camera_boundary) {
+        let boundary = &camera_boundary.0;
+        spawn_line(
+            &mut commands,
+            Vec2::new(ARRIVE_TRIGGER_X, (boundary.bottom + boundary.top) / 2.0),
+            Vec2::new(LINE_WIDTH, boundary.top - boundary.bottom),
+            &debug_materials.trigger,
+        );
+    }
+
+    if let Some(spawn_point) = spawn_point {
+        spawn_line(
+            &mut commands,
+            spawn_point.0,
+            Vec2::splat(MARKER_SIZE),
+            &debug_materials.spawn_point,
+        );
+    }
+}
+
+fn update_debug_panel_system(
+    state: Res<State<LevelState>>,
+    dialogue_state: Res<DialogueState>,
+    diagnostics: Option<Res<Diagnostics>>,
+    santa_query: Query<(&Position, &Speed, &GroundState), With<Santa>>,
+    mut panel_query: Query<&mut Text, With<DebugPanel>>,
+) {
+    let fps = diagnostics
+        .as_ref()
+        .and_then(|diagnostics| diagnostics.get(FrameTimeDiagnosticsPlugin::FPS))
+        .and_then(|fps| fps.average());
+
+    for mut text in panel_query.iter_mut() {
+        let mut lines = vec![match fps {
+            Some(fps) => format!("FPS: {:.0}", fps),
+            None => "FPS: -".to_owned(),
+        }];
+        for (position, speed, ground_state) in santa_query.iter() {
+            lines.push(format!(
+                "Position: ({:.1}, {:.1})",
+                position.0.x, position.0.y
+            ));
+            lines.push(format!("Speed: ({:.1}, {:.1})", speed.0.x, speed.0.y));
+            lines.push(format!(
+                "On ground: {}, just landed: {}",
+                ground_state.on_ground, ground_state.just_landed
+            ));
+        }
+        lines.push(format!("Level: {:?}", state.current()));
+        lines.push(format!("Dialogue: {:?}", *dialogue_state));
+
+        text.sections[0].value = lines.join("\n");
+    }
+}
+
+pub struct DebugOverlayPlugin;
+
+impl Plugin for DebugOverlayPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<DebugOverlay>()
+            .init_resource::<DebugMaterials>()
+            .add_system(
+                toggle_debug_overlay_system
+                    .system()
+                    .label("toggle_debug_overlay"),
+            )
+            .add_system(
+                draw_debug_shapes_system
+                    .system()
+                    .label("draw_debug_shapes")
+                    .after("toggle_debug_overlay")
+                    .after("level_boundary"),
+            )
+            .add_system(
+                update_debug_panel_system
+                    .system()
+                    .label("update_debug_panel")
+                    .after("toggle_debug_overlay")
+                    .after("level_boundary"),
+            );
+    }
+}
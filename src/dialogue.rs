@@ -1,20 +1,81 @@
-use crate::assets::{AssetsReady, SantaAssets};
-use crate::levels::IndoorsLevel;
+use crate::assets::{AssetsReady, Portrait, SantaAssets};
+use crate::audio::{SantaAudio, VoiceLine};
+use crate::cli::CliArgs;
+use crate::config::UserConfig;
+use crate::console::{is_console_open, Console, ConsoleAppExt};
+use crate::controls::{key_name, Action, ActionState, KeyBindings};
+use crate::dialogue_graph::{ChoiceMenu, DialogueFlags, DialogueRunner};
+use crate::levels::{IndoorsLevel, LevelStage, LevelState};
+use crate::npc::NpcEvent;
 use crate::physics::{GroundState, Position};
 use crate::player::Santa;
+use crate::TIME_STEP;
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
-use std::collections::VecDeque;
+use bevy::render::texture::{Extent3d, TextureDimension};
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+const BOX_WIDTH_FRACTION: f32 = 0.9;
+const BOX_HEIGHT: f32 = 160.0;
+const BOX_PADDING: f32 = 12.0;
+const MAX_FONT_SIZE: f32 = 64.0;
+const MIN_FONT_SIZE: f32 = 16.0;
+const FONT_SIZE_STEP: f32 = 4.0;
+const GLYPH_WIDTH: f32 = 0.6;
+const LINE_HEIGHT: f32 = 1.2;
+const HINT_SCALE: f32 = 0.5;
+const REVEAL_FRACTION: f32 = 0.9;
+const AUTO_ADVANCE_DELAY: f32 = 0.5;
+const HISTORY_LINES: usize = 12;
+pub const ARRIVE_TRIGGER_X: f32 = 100.0;
 
 #[derive(Default)]
 pub struct DialogueQueue {
     backlog: VecDeque<String>,
 }
 
-pub struct ActiveDialogue;
+impl DialogueQueue {
+    pub fn push(&mut self, key: impl Into<String>) {
+        self.backlog.push_back(key.into());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.backlog.is_empty()
+    }
+}
+
+pub struct ActiveDialogue {
+    text: String,
+    revealed_chars: usize,
+    reveal_secs: f32,
+    duration: f32,
+    elapsed: f32,
+    skip_reveal: bool,
+    voice: Option<VoiceLine>,
+}
+
+pub struct DialogueText;
+
+pub struct DialogueStarted(pub String);
+
+#[derive(Default)]
+pub struct DialogueHistory(pub Vec<String>);
+
+pub struct HistoryLog;
+
+#[derive(Default)]
+pub struct DialogueSettings {
+    pub auto_advance: bool,
+}
+
+#[derive(Default)]
+pub struct PortraitMaterials(HashMap<(Handle<TextureAtlas>, u32), (Handle<ColorMaterial>, Vec2)>);
 
 #[derive(Default)]
 pub struct DialogueTimer(pub Timer);
 
+#[derive(Debug)]
 pub enum DialogueState {
     Hello,
     Tutorial,
@@ -28,7 +89,6 @@ fn dialogue_setup_system(mut commands: Commands) {
 }
 
 fn dialogue_trigger_system(
-    time: Res<Time>,
     mut dialogue_state: ResMut<DialogueState>,
     mut dialogue_queue: ResMut<DialogueQueue>,
     mut dialogue_timer: ResMut<DialogueTimer>,
@@ -36,8 +96,10 @@ fn dialogue_trigger_system(
     santa_query: Query<(&Position, &GroundState), With<Santa>>,
     active_dialogue_query: Query<Entity, With<ActiveDialogue>>,
     indoors_level_query: Query<(), With<IndoorsLevel>>,
+    mut npc_events: EventReader<NpcEvent>,
+    dialogue_flags: Res<DialogueFlags>,
 ) {
-    dialogue_timer.0.tick(time.delta());
+    dialogue_timer.0.tick(Duration::from_secs_f32(TIME_STEP));
     let has_active_dialogue = active_dialogue_query.iter().next().is_some();
     let on_ground = santa_query
         .iter()
@@ -70,7 +132,7 @@ fn dialogue_trigger_system(
 
         DialogueState::Arrive => {
             if !has_active_dialogue
-                && position.0.x >= 100.0
+                && position.0.x >= ARRIVE_TRIGGER_X
                 && dialogue_timer.0.elapsed_secs() > 1.0
             {
                 dialogue_queue.backlog.push_back("arrive_1".to_owned());
@@ -81,6 +143,11 @@ fn dialogue_trigger_system(
         DialogueState::EnterHouse => {
             if indoors {
                 dialogue_queue.backlog.push_back("enter_house_1".to_owned());
+                if dialogue_flags.is_set("elf_warned") {
+                    dialogue_queue
+                        .backlog
+                        .push_back("remember_warning_1".to_owned());
+                }
                 *dialogue_state = DialogueState::Finished;
             }
         }
@@ -89,34 +156,172 @@ fn dialogue_trigger_system(
             // Done
         }
     }
+
+    for npc_event in npc_events.iter() {
+        match npc_event {
+            NpcEvent::Spotted(_) => dialogue_queue.backlog.push_back("spotted_1".to_owned()),
+            NpcEvent::Caught(_) => dialogue_queue.backlog.push_back("caught_1".to_owned()),
+            NpcEvent::WokeUp(_) | NpcEvent::LostSight(_) => {}
+        }
+    }
 }
 
+fn wrap_text(text: &str, max_chars: usize) -> String {
+    let mut wrapped = String::new();
+    for paragraph in text.split('\n') {
+        let mut line_length = 0;
+        if !wrapped.is_empty() {
+            wrapped.push('\n');
+        }
+
+        for word in paragraph.split_whitespace() {
+            let word_length = word.chars().count();
+            if line_length > 0 && line_length + 1 + word_length > max_chars {
+                wrapped.push('\n');
+                line_length = 0;
+            } else if line_length > 0 {
+                wrapped.push(' ');
+                line_length += 1;
+            }
+            wrapped.push_str(word);
+            line_length += word_length;
+        }
+    }
+    wrapped
+}
+
+fn fit_text(text: &str, width: f32, height: f32) -> (String, f32) {
+    let mut font_size = MAX_FONT_SIZE;
+    loop {
+        let max_chars = ((width / (font_size * GLYPH_WIDTH)).floor() as usize).max(1);
+        let wrapped = wrap_text(text, max_chars);
+        // One extra line for the hint below the text.
+        let line_count = wrapped.lines().count() as f32 + HINT_SCALE;
+        if line_count * font_size * LINE_HEIGHT <= height || font_size <= MIN_FONT_SIZE {
+            return (wrapped, font_size);
+        }
+        font_size -= FONT_SIZE_STEP;
+    }
+}
+
+#[derive(SystemParam)]
+struct PortraitParams<'a> {
+    texture_atlases: Res<'a, Assets<TextureAtlas>>,
+    textures: ResMut<'a, Assets<Texture>>,
+    portrait_materials: ResMut<'a, PortraitMaterials>,
+}
+
+fn portrait_material(
+    portrait: &Portrait,
+    portrait_params: &mut PortraitParams,
+    materials: &mut Assets<ColorMaterial>,
+) -> Option<(Handle<ColorMaterial>, Vec2)> {
+    let key = (portrait.atlas.clone(), portrait.index);
+    if let Some(cached) = portrait_params.portrait_materials.0.get(&key) {
+        return Some(cached.clone());
+    }
+
+    let atlas = portrait_params.texture_atlases.get(&portrait.atlas)?;
+    let rect = atlas.textures.get(portrait.index as usize)?;
+    let texture = portrait_params.textures.get(&atlas.texture)?;
+
+    let pixel_size = texture.format.pixel_size();
+    let texture_width = texture.size.width as usize;
+    let (min_x, min_y) = (rect.min.x as usize, rect.min.y as usize);
+    let (width, height) = (rect.width() as usize, rect.height() as usize);
+
+    let mut data = Vec::with_capacity(width * height * pixel_size);
+    for y in min_y..min_y + height {
+        let start = (y * texture_width + min_x) * pixel_size;
+        data.extend_from_slice(&texture.data[start..start + width * pixel_size]);
+    }
+
+    let cropped = Texture::new(
+        Extent3d::new(width as u32, height as u32, 1),
+        TextureDimension::D2,
+        data,
+        texture.format,
+    );
+    let material = materials.add(portrait_params.textures.add(cropped).into());
+    let result = (material, Vec2::new(width as f32, height as f32));
+    portrait_params
+        .portrait_materials
+        .0
+        .insert(key, result.clone());
+    Some(result)
+}
+
+#[allow(clippy::too_many_arguments)]
 fn dialogue_execution_system(
     mut commands: Commands,
-    audio: Res<Audio>,
-    keyboard_input: Res<Input<KeyCode>>,
+    mut santa_audio: NonSendMut<SantaAudio>,
+    audio_sources: Res<Assets<AudioSource>>,
+    action_state: Res<ActionState>,
+    key_bindings: Res<KeyBindings>,
+    dialogue_settings: Res<DialogueSettings>,
+    windows: Res<Windows>,
     mut dialogue_queue: ResMut<DialogueQueue>,
-    active_dialogue_query: Query<Entity, With<ActiveDialogue>>,
+    mut dialogue_history: ResMut<DialogueHistory>,
+    mut active_dialogue_query: Query<(Entity, &mut ActiveDialogue)>,
     santa_assets: Res<SantaAssets>,
     mut materials: ResMut<Assets<ColorMaterial>>,
+    mut portrait_params: PortraitParams,
     mut dialogue_timer: ResMut<DialogueTimer>,
+    mut dialogue_started_events: EventWriter<DialogueStarted>,
 ) {
-    let next = keyboard_input.just_released(KeyCode::P);
-    let mut has_active_dialogue = active_dialogue_query.iter().next().is_some();
+    let next = action_state.just_released(Action::NextDialogue);
+    let mut has_active_dialogue = false;
 
-    if next && has_active_dialogue {
-        for active_dialogue in active_dialogue_query.iter() {
-            commands.entity(active_dialogue).despawn_recursive();
+    for (entity, mut active_dialogue) in active_dialogue_query.iter_mut() {
+        let fully_revealed = active_dialogue.revealed_chars >= active_dialogue.text.len();
+        let clip_finished =
+            active_dialogue.elapsed >= active_dialogue.duration + AUTO_ADVANCE_DELAY;
+
+        if next && !fully_revealed {
+            active_dialogue.skip_reveal = true;
+            has_active_dialogue = true;
+        } else if next || (dialogue_settings.auto_advance && fully_revealed && clip_finished) {
+            if let Some(voice) = active_dialogue.voice {
+                santa_audio.stop_voice_line(voice);
+            }
+            commands.entity(entity).despawn_recursive();
+            dialogue_timer.0.reset();
+        } else {
+            has_active_dialogue = true;
         }
-        has_active_dialogue = false;
-        dialogue_timer.0.reset();
     }
 
     if !has_active_dialogue {
         if let Some(next_dialogue_key) = dialogue_queue.backlog.pop_front() {
             let speech = santa_assets.speech.get(&next_dialogue_key).unwrap();
-            audio.play(speech.audio.clone());
+            let voice = speech
+                .audio
+                .as_ref()
+                .and_then(|speech_audio| audio_sources.get(speech_audio))
+                .and_then(|audio_source| santa_audio.play_voice(audio_source));
             dialogue_timer.0.reset();
+            dialogue_started_events.send(DialogueStarted(next_dialogue_key.clone()));
+            dialogue_history.0.push(next_dialogue_key);
+
+            let window_width = windows
+                .get_primary()
+                .map(|window| window.width())
+                .unwrap_or(800.0);
+            let box_width = window_width * BOX_WIDTH_FRACTION;
+
+            let portrait = speech.portrait.as_ref().and_then(|portrait| {
+                portrait_material(portrait, &mut portrait_params, &mut materials)
+            });
+            let portrait_width = portrait
+                .as_ref()
+                .map(|(_, size)| size.x / size.y * (BOX_HEIGHT - 2.0 * BOX_PADDING) + BOX_PADDING)
+                .unwrap_or(0.0);
+
+            let (wrapped_text, font_size) = fit_text(
+                &speech.text,
+                box_width - portrait_width - 2.0 * BOX_PADDING,
+                BOX_HEIGHT - 2.0 * BOX_PADDING,
+            );
 
             commands
                 .spawn_bundle(NodeBundle {
@@ -129,14 +334,23 @@ fn dialogue_execution_system(
                     material: materials.add(Color::NONE.into()),
                     ..Default::default()
                 })
-                .insert(ActiveDialogue)
+                .insert(ActiveDialogue {
+                    text: wrapped_text,
+                    revealed_chars: 0,
+                    reveal_secs: speech.duration * REVEAL_FRACTION,
+                    duration: speech.duration,
+                    elapsed: 0.0,
+                    skip_reveal: false,
+                    voice,
+                })
                 .with_children(|parent| {
                     parent
                         .spawn_bundle(NodeBundle {
                             style: Style {
-                                size: Size::new(Val::Percent(90.0), Val::Px(160.0)),
-                                justify_content: JustifyContent::Center,
-                                align_content: AlignContent::Center,
+                                size: Size::new(Val::Px(box_width), Val::Px(BOX_HEIGHT)),
+                                padding: Rect::all(Val::Px(BOX_PADDING)),
+                                justify_content: JustifyContent::FlexStart,
+                                align_items: AlignItems::Center,
                                 align_self: AlignSelf::Center,
                                 ..Default::default()
                             },
@@ -144,54 +358,364 @@ fn dialogue_execution_system(
                             ..Default::default()
                         })
                         .with_children(|parent| {
-                            parent.spawn_bundle(TextBundle {
-                                style: Style {
-                                    align_self: AlignSelf::Center,
-                                    position_type: PositionType::Absolute,
-                                    ..Default::default()
-                                },
-                                // Use the `Text::with_section` constructor
-                                text: Text::with_section(
-                                    // Accepts a `String` or any type that converts into a `String`, such as `&str`
-                                    format!("{}\nPress <P>", speech.text),
-                                    TextStyle {
-                                        font: santa_assets.font.clone(),
-                                        font_size: 64.0,
-                                        color: Color::BLACK,
-                                    },
-                                    // Note: You can use `Default::default()` in place of the `TextAlignment`
-                                    TextAlignment {
-                                        horizontal: HorizontalAlign::Center,
+                            if let Some((material, size)) = portrait {
+                                let height = BOX_HEIGHT - 2.0 * BOX_PADDING;
+                                parent.spawn_bundle(ImageBundle {
+                                    style: Style {
+                                        size: Size::new(
+                                            Val::Px(size.x / size.y * height),
+                                            Val::Px(height),
+                                        ),
+                                        margin: Rect {
+                                            right: Val::Px(BOX_PADDING),
+                                            ..Default::default()
+                                        },
                                         ..Default::default()
                                     },
-                                ),
-                                ..Default::default()
-                            });
+                                    material,
+                                    ..Default::default()
+                                });
+                            }
+
+                            parent
+                                .spawn_bundle(TextBundle {
+                                    text: Text {
+                                        sections: vec![
+                                            TextSection {
+                                                value: String::new(),
+                                                style: TextStyle {
+                                                    font: santa_assets.font.clone(),
+                                                    font_size,
+                                                    color: Color::BLACK,
+                                                },
+                                            },
+                                            TextSection {
+                                                value: format!(
+                                                    "\nPress <{}>",
+                                                    key_name(key_bindings.next_dialogue)
+                                                ),
+                                                style: TextStyle {
+                                                    font: santa_assets.font.clone(),
+                                                    font_size: font_size * HINT_SCALE,
+                                                    color: Color::DARK_GRAY,
+                                                },
+                                            },
+                                        ],
+                                        alignment: TextAlignment {
+                                            horizontal: HorizontalAlign::Left,
+                                            ..Default::default()
+                                        },
+                                    },
+                                    ..Default::default()
+                                })
+                                .insert(DialogueText);
                         });
                 });
         }
     }
 }
 
+#[allow(clippy::too_many_arguments)]
+fn dialogue_controls_system(
+    mut commands: Commands,
+    action_state: Res<ActionState>,
+    mut santa_audio: NonSendMut<SantaAudio>,
+    audio_sources: Res<Assets<AudioSource>>,
+    santa_assets: Res<SantaAssets>,
+    mut dialogue_queue: ResMut<DialogueQueue>,
+    dialogue_history: Res<DialogueHistory>,
+    mut dialogue_timer: ResMut<DialogueTimer>,
+    mut dialogue_runner: ResMut<DialogueRunner>,
+    mut active_dialogue_query: Query<(Entity, &mut ActiveDialogue)>,
+    choice_menu_query: Query<Entity, With<ChoiceMenu>>,
+) {
+    if action_state.just_released(Action::SkipDialogue) {
+        dialogue_queue.backlog.clear();
+        for (active_dialogue, _) in active_dialogue_query.iter_mut() {
+            commands.entity(active_dialogue).despawn_recursive();
+        }
+        // Otherwise the conversation would just queue its next line or choice.
+        dialogue_runner.stop();
+        for choice_menu in choice_menu_query.iter() {
+            commands.entity(choice_menu).despawn_recursive();
+        }
+        santa_audio.stop_voice();
+        dialogue_timer.0.reset();
+    }
+
+    if action_state.just_released(Action::ReplayDialogue) {
+        if let Some(audio_source) = dialogue_history
+            .0
+            .last()
+            .and_then(|key| santa_assets.speech.get(key))
+            .and_then(|speech| speech.audio.as_ref())
+            .and_then(|speech_audio| audio_sources.get(speech_audio))
+        {
+            let voice = santa_audio.play_voice(audio_source);
+            // Advancing stops the voice line of the active dialogue, so it owns the replay.
+            for (_, mut active_dialogue) in active_dialogue_query.iter_mut() {
+                active_dialogue.voice = voice;
+            }
+        }
+    }
+}
+
+fn interrupt_dialogue_system(
+    mut commands: Commands,
+    mut santa_audio: NonSendMut<SantaAudio>,
+    mut dialogue_timer: ResMut<DialogueTimer>,
+    active_dialogue_query: Query<Entity, With<ActiveDialogue>>,
+) {
+    for entity in active_dialogue_query.iter() {
+        commands.entity(entity).despawn_recursive();
+        dialogue_timer.0.reset();
+    }
+    // Also stops a line replayed after its dialogue was closed.
+    santa_audio.stop_voice();
+}
+
+fn dialogue_history_system(
+    mut commands: Commands,
+    action_state: Res<ActionState>,
+    santa_assets: Res<SantaAssets>,
+    dialogue_history: Res<DialogueHistory>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    history_log_query: Query<Entity, With<HistoryLog>>,
+) {
+    let is_open = history_log_query.iter().next().is_some();
+    let toggle = action_state.just_released(Action::DialogueHistory);
+    if !toggle && !(is_open && dialogue_history.is_changed()) {
+        return;
+    }
+
+    for history_log in history_log_query.iter() {
+        commands.entity(history_log).despawn_recursive();
+    }
+    if toggle && is_open {
+        return;
+    }
+
+    let lines: Vec<&str> = dialogue_history
+        .0
+        .iter()
+        .rev()
+        .take(HISTORY_LINES)
+        .rev()
+        .filter_map(|key| santa_assets.speech.get(key))
+        .map(|speech| speech.text.as_str())
+        .collect();
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::FlexEnd,
+                ..Default::default()
+            },
+            material: materials.add(Color::NONE.into()),
+            ..Default::default()
+        })
+        .insert(HistoryLog)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(BOX_WIDTH_FRACTION * 100.0), Val::Auto),
+                        flex_direction: FlexDirection::ColumnReverse,
+                        padding: Rect::all(Val::Px(BOX_PADDING)),
+                        margin: Rect {
+                            top: Val::Px(BOX_PADDING),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    material: materials.add(Color::rgba(0.0, 0.0, 0.0, 0.8).into()),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    if lines.is_empty() {
+                        spawn_history_line(parent, &santa_assets, "(nothing said yet)");
+                    }
+                    for line in lines {
+                        spawn_history_line(parent, &santa_assets, line);
+                    }
+                });
+        });
+}
+
+fn spawn_history_line(parent: &mut ChildBuilder, santa_assets: &SantaAssets, line: &str) {
+    parent.spawn_bundle(TextBundle {
+        text: Text::with_section(
+            line,
+            TextStyle {
+                font: santa_assets.font.clone(),
+                font_size: 20.0,
+                color: Color::WHITE,
+            },
+            Default::default(),
+        ),
+        ..Default::default()
+    });
+}
+
+fn dialogue_reveal_system(
+    mut active_dialogue_query: Query<(&mut ActiveDialogue, &Children)>,
+    box_query: Query<&Children>,
+    mut text_query: Query<&mut Text, With<DialogueText>>,
+) {
+    for (mut active_dialogue, children) in active_dialogue_query.iter_mut() {
+        active_dialogue.elapsed += TIME_STEP;
+
+        let total_chars = active_dialogue.text.len();
+        let revealed_chars = if active_dialogue.skip_reveal || active_dialogue.reveal_secs <= 0.0 {
+            total_chars
+        } else {
+            ((active_dialogue.elapsed / active_dialogue.reveal_secs * total_chars as f32) as usize)
+                .min(total_chars)
+        };
+        if revealed_chars == active_dialogue.revealed_chars {
+            continue;
+        }
+        active_dialogue.revealed_chars = revealed_chars;
+
+        // `revealed_chars` counts bytes, so step back to the nearest character boundary.
+        let mut end = revealed_chars;
+        while !active_dialogue.text.is_char_boundary(end) {
+            end -= 1;
+        }
+        let revealed_text = &active_dialogue.text[..end];
+
+        for dialogue_box in children.iter() {
+            if let Ok(box_children) = box_query.get(*dialogue_box) {
+                for child in box_children.iter() {
+                    if let Ok(mut text) = text_query.get_mut(*child) {
+                        text.sections[0].value = revealed_text.to_owned();
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn apply_dialogue_settings_system(
+    config: Option<Res<UserConfig>>,
+    mut dialogue_settings: ResMut<DialogueSettings>,
+) {
+    if let Some(config) = config {
+        if config.is_changed() {
+            dialogue_settings.auto_advance = config.dialogue.auto_advance;
+        }
+    }
+}
+
+fn auto_advance_key_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    console: Option<Res<Console>>,
+    config: Option<ResMut<UserConfig>>,
+) {
+    if is_console_open(console) {
+        return;
+    }
+    if let Some(mut config) = config {
+        if keyboard_input.just_released(key_bindings.auto_advance) {
+            config.dialogue.auto_advance = !config.dialogue.auto_advance;
+            info!(
+                "Dialogue auto-advance set to {}",
+                config.dialogue.auto_advance
+            );
+        }
+    }
+}
+
+fn say_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let key = *args
+        .first()
+        .ok_or_else(|| "Missing dialogue key".to_owned())?;
+    let known = world
+        .get_resource::<SantaAssets>()
+        .map_or(false, |santa_assets| santa_assets.speech.contains_key(key));
+    if !known {
+        return Err(format!("Unknown dialogue {}", key));
+    }
+
+    world.get_resource_mut::<DialogueQueue>().unwrap().push(key);
+    Ok(format!("Queued {}", key))
+}
+
 pub struct DialoguePlugin;
 
 impl Plugin for DialoguePlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.insert_resource(DialogueState::Hello)
+        let cli_args = app
+            .world()
+            .get_resource::<CliArgs>()
+            .cloned()
+            .unwrap_or_default();
+        // Starting indoors skips the outdoor lines, which would not make sense there.
+        let dialogue_state = match cli_args.level {
+            Some(LevelState::Indoors) => DialogueState::EnterHouse,
+            _ if cli_args.skip_intro => DialogueState::Arrive,
+            _ => DialogueState::Hello,
+        };
+
+        app.insert_resource(dialogue_state)
             .insert_resource(DialogueQueue::default())
             .insert_resource(DialogueTimer(Timer::from_seconds(99999999.0, true)))
+            .init_resource::<DialogueSettings>()
+            .init_resource::<PortraitMaterials>()
+            .init_resource::<DialogueHistory>()
+            .add_event::<DialogueStarted>()
+            .add_console_command("say", "say KEY - queue a dialogue line", say_command)
             .add_startup_system(dialogue_setup_system.system().label("dialogue_setup"))
+            .add_system_set_to_stage(
+                LevelStage,
+                SystemSet::on_exit(LevelState::Outside)
+                    .with_system(interrupt_dialogue_system.system()),
+            )
+            .add_system_set_to_stage(
+                LevelStage,
+                SystemSet::on_exit(LevelState::Indoors)
+                    .with_system(interrupt_dialogue_system.system()),
+            )
+            .add_system(auto_advance_key_system.system().label("auto_advance_key"))
+            .add_system(
+                apply_dialogue_settings_system
+                    .system()
+                    .label("apply_dialogue_settings")
+                    .after("auto_advance_key")
+                    .before("dialogue_execution"),
+            )
             .add_system(
                 dialogue_trigger_system
                     .system()
                     .label("dialogue_trigger")
                     .after("dialogue_setup"),
             )
+            .add_system(
+                dialogue_controls_system
+                    .system()
+                    .label("dialogue_controls")
+                    .after("dialogue_trigger"),
+            )
             .add_system(
                 dialogue_execution_system
                     .system()
                     .label("dialogue_execution")
-                    .after("dialogue_trigger"),
+                    .after("dialogue_controls"),
+            )
+            .add_system(
+                dialogue_history_system
+                    .system()
+                    .label("dialogue_history")
+                    .after("dialogue_execution"),
+            )
+            .add_system(
+                dialogue_reveal_system
+                    .system()
+                    .label("dialogue_reveal")
+                    .after("dialogue_execution"),
             );
     }
 }
@@ -0,0 +1,446 @@
+use crate::assets::{add_silent_speech, load_data, SantaAssets};
+use crate::console::ConsoleAppExt;
+use crate::controls::{Action, ActionState};
+use crate::dialogue::{ActiveDialogue, DialogueQueue};
+use crate::interaction::{Interacted, InteractionAction};
+use bevy::prelude::*;
+use serde_derive::Deserialize;
+use std::collections::{HashMap, HashSet};
+
+const DIALOGUE_GRAPHS: &[&str] = &["elf"];
+
+#[derive(Deserialize)]
+pub struct GraphLine {
+    pub speaker: Option<String>,
+    pub text: String,
+}
+
+#[derive(Deserialize)]
+pub enum DialogueCondition {
+    FlagSet(String),
+    FlagUnset(String),
+}
+
+#[derive(Deserialize, Clone)]
+pub enum DialogueEffect {
+    SetFlag(String),
+    ClearFlag(String),
+}
+
+#[derive(Deserialize)]
+pub struct DialogueChoice {
+    pub text: String,
+    #[serde(default)]
+    pub conditions: Vec<DialogueCondition>,
+    #[serde(default)]
+    pub effects: Vec<DialogueEffect>,
+    #[serde(default)]
+    pub next: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct DialogueNode {
+    #[serde(default)]
+    pub lines: Vec<String>,
+    #[serde(default)]
+    pub effects: Vec<DialogueEffect>,
+    #[serde(default)]
+    pub choices: Vec<DialogueChoice>,
+    #[serde(default)]
+    pub next: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct DialogueGraph {
+    pub start: String,
+    #[serde(default)]
+    pub lines: HashMap<String, GraphLine>,
+    pub nodes: HashMap<String, DialogueNode>,
+}
+
+#[derive(Default)]
+pub struct DialogueGraphs(pub HashMap<String, DialogueGraph>);
+
+#[derive(Default)]
+pub struct DialogueFlags(pub HashSet<String>);
+
+impl DialogueFlags {
+    pub fn is_set(&self, flag: &str) -> bool {
+        self.0.contains(flag)
+    }
+
+    fn fulfils(&self, condition: &DialogueCondition) -> bool {
+        match condition {
+            DialogueCondition::FlagSet(flag) => self.is_set(flag),
+            DialogueCondition::FlagUnset(flag) => !self.is_set(flag),
+        }
+    }
+
+    fn apply(&mut self, effects: &[DialogueEffect]) {
+        for effect in effects {
+            match effect {
+                DialogueEffect::SetFlag(flag) => {
+                    self.0.insert(flag.clone());
+                }
+                DialogueEffect::ClearFlag(flag) => {
+                    self.0.remove(flag);
+                }
+            }
+        }
+    }
+}
+
+struct Conversation {
+    graph: String,
+    node: String,
+    choices: Option<Vec<usize>>,
+    selected: usize,
+}
+
+#[derive(Default)]
+pub struct DialogueRunner {
+    conversation: Option<Conversation>,
+}
+
+impl DialogueRunner {
+    pub fn is_running(&self) -> bool {
+        self.conversation.is_some()
+    }
+
+    pub fn stop(&mut self) {
+        self.conversation = None;
+    }
+}
+
+pub struct ChoiceMenu;
+
+pub struct ChoiceEntry(usize);
+
+fn load_dialogue_graphs_system(
+    mut dialogue_graphs: ResMut<DialogueGraphs>,
+    mut santa_assets: ResMut<SantaAssets>,
+) {
+    for name in DIALOGUE_GRAPHS {
+        let graph: DialogueGraph = match load_data(&format!("dialogue/{}.ron", name)) {
+            Some(graph) => graph,
+            None => continue,
+        };
+
+        for (key, line) in &graph.lines {
+            let portrait = line
+                .speaker
+                .as_ref()
+                .and_then(|speaker| santa_assets.portraits.get(speaker))
+                .cloned();
+            add_silent_speech(&mut santa_assets, key, line.text.clone(), portrait);
+        }
+
+        info!(
+            "Loaded dialogue graph {} with {} nodes",
+            name,
+            graph.nodes.len()
+        );
+        dialogue_graphs.0.insert(name.to_string(), graph);
+    }
+}
+
+fn enter_node(
+    dialogue_runner: &mut DialogueRunner,
+    dialogue_graphs: &DialogueGraphs,
+    dialogue_flags: &mut DialogueFlags,
+    dialogue_queue: &mut DialogueQueue,
+    graph_name: &str,
+    node_name: Option<&str>,
+) {
+    let graph = dialogue_graphs.0.get(graph_name);
+    let node = node_name.and_then(|node_name| graph?.nodes.get(node_name));
+    let (node_name, node) = match (node_name, node) {
+        (Some(node_name), Some(node)) => (node_name, node),
+        (Some(node_name), None) => {
+            error!("Unknown dialogue node {}/{}", graph_name, node_name);
+            dialogue_runner.conversation = None;
+            return;
+        }
+        _ => {
+            dialogue_runner.conversation = None;
+            return;
+        }
+    };
+
+    dialogue_flags.apply(&node.effects);
+    for line in &node.lines {
+        dialogue_queue.push(line.as_str());
+    }
+    dialogue_runner.conversation = Some(Conversation {
+        graph: graph_name.to_owned(),
+        node: node_name.to_owned(),
+        choices: None,
+        selected: 0,
+    });
+}
+
+fn start_conversation_system(
+    mut dialogue_runner: ResMut<DialogueRunner>,
+    dialogue_graphs: Res<DialogueGraphs>,
+    mut dialogue_flags: ResMut<DialogueFlags>,
+    mut dialogue_queue: ResMut<DialogueQueue>,
+    mut interacted_events: EventReader<Interacted>,
+) {
+    for interacted in interacted_events.iter() {
+        if let InteractionAction::Talk(graph_name) = &interacted.action {
+            if dialogue_runner.is_running() {
+                continue;
+            }
+
+            let start = match dialogue_graphs.0.get(graph_name) {
+                Some(graph) => graph.start.clone(),
+                None => {
+                    error!("Unknown dialogue graph {}", graph_name);
+                    continue;
+                }
+            };
+            enter_node(
+                &mut dialogue_runner,
+                &dialogue_graphs,
+                &mut dialogue_flags,
+                &mut dialogue_queue,
+                graph_name,
+                Some(&start),
+            );
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn conversation_system(
+    mut commands: Commands,
+    mut dialogue_runner: ResMut<DialogueRunner>,
+    dialogue_graphs: Res<DialogueGraphs>,
+    mut dialogue_flags: ResMut<DialogueFlags>,
+    mut dialogue_queue: ResMut<DialogueQueue>,
+    santa_assets: Res<SantaAssets>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    active_dialogue_query: Query<(), With<ActiveDialogue>>,
+) {
+    let has_active_dialogue = active_dialogue_query.iter().next().is_some();
+    if has_active_dialogue || !dialogue_queue.is_empty() {
+        return;
+    }
+
+    let conversation = match &mut dialogue_runner.conversation {
+        Some(conversation) if conversation.choices.is_none() => conversation,
+        _ => return,
+    };
+    let node = match dialogue_graphs
+        .0
+        .get(&conversation.graph)
+        .and_then(|graph| graph.nodes.get(&conversation.node))
+    {
+        Some(node) => node,
+        None => {
+            dialogue_runner.conversation = None;
+            return;
+        }
+    };
+
+    let available_choices: Vec<usize> = node
+        .choices
+        .iter()
+        .enumerate()
+        .filter(|(_, choice)| {
+            choice
+                .conditions
+                .iter()
+                .all(|condition| dialogue_flags.fulfils(condition))
+        })
+        .map(|(index, _)| index)
+        .collect();
+
+    if available_choices.is_empty() {
+        let graph_name = conversation.graph.clone();
+        let next = node.next.clone();
+        enter_node(
+            &mut dialogue_runner,
+            &dialogue_graphs,
+            &mut dialogue_flags,
+            &mut dialogue_queue,
+            &graph_name,
+            next.as_deref(),
+        );
+        return;
+    }
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::FlexEnd,
+                ..Default::default()
+            },
+            material: materials.add(Color::NONE.into()),
+            ..Default::default()
+        })
+        .insert(ChoiceMenu)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::ColumnReverse,
+                        padding: Rect::all(Val::Px(12.0)),
+                        margin: Rect {
+                            bottom: Val::Px(24.0),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    material: materials.add(Color::ANTIQUE_WHITE.into()),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    for &index in &available_choices {
+                        parent
+                            .spawn_bundle(TextBundle {
+                                text: Text::with_section(
+                                    node.choices[index].text.clone(),
+                                    TextStyle {
+                                        font: santa_assets.font.clone(),
+                                        font_size: 32.0,
+                                        color: Color::BLACK,
+                                    },
+                                    Default::default(),
+                                ),
+                                ..Default::default()
+                            })
+                            .insert(ChoiceEntry(index));
+                    }
+                });
+        });
+
+    conversation.choices = Some(available_choices);
+    conversation.selected = 0;
+}
+
+fn choice_input_system(
+    mut commands: Commands,
+    action_state: Res<ActionState>,
+    mut dialogue_runner: ResMut<DialogueRunner>,
+    dialogue_graphs: Res<DialogueGraphs>,
+    mut dialogue_flags: ResMut<DialogueFlags>,
+    mut dialogue_queue: ResMut<DialogueQueue>,
+    choice_menu_query: Query<Entity, With<ChoiceMenu>>,
+    mut choice_entry_query: Query<(&ChoiceEntry, &mut Text)>,
+) {
+    let conversation = match &mut dialogue_runner.conversation {
+        Some(conversation) => conversation,
+        None => return,
+    };
+    let choices = match &conversation.choices {
+        Some(choices) => choices.clone(),
+        None => return,
+    };
+
+    let up = action_state.just_pressed(Action::MenuUp);
+    let down = action_state.just_pressed(Action::MenuDown);
+    let confirm = action_state.just_pressed(Action::MenuConfirm);
+
+    if up {
+        conversation.selected = (conversation.selected + choices.len() - 1) % choices.len();
+    }
+    if down {
+        conversation.selected = (conversation.selected + 1) % choices.len();
+    }
+
+    let selected_choice = choices[conversation.selected];
+    for (choice_entry, mut text) in choice_entry_query.iter_mut() {
+        text.sections[0].style.color = if choice_entry.0 == selected_choice {
+            Color::RED
+        } else {
+            Color::BLACK
+        };
+    }
+
+    if confirm {
+        for choice_menu in choice_menu_query.iter() {
+            commands.entity(choice_menu).despawn_recursive();
+        }
+
+        let graph_name = conversation.graph.clone();
+        let choice = dialogue_graphs
+            .0
+            .get(&graph_name)
+            .and_then(|graph| graph.nodes.get(&conversation.node))
+            .map(|node| &node.choices[selected_choice]);
+        let next = match choice {
+            Some(choice) => {
+                dialogue_flags.apply(&choice.effects);
+                choice.next.clone()
+            }
+            None => None,
+        };
+
+        enter_node(
+            &mut dialogue_runner,
+            &dialogue_graphs,
+            &mut dialogue_flags,
+            &mut dialogue_queue,
+            &graph_name,
+            next.as_deref(),
+        );
+    }
+}
+
+fn flag_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let mut flags = world.get_resource_mut::<DialogueFlags>().unwrap();
+    match args {
+        ["set", flag] => {
+            flags.0.insert(flag.to_string());
+            Ok(format!("Set {}", flag))
+        }
+        ["clear", flag] => {
+            flags.0.remove(*flag);
+            Ok(format!("Cleared {}", flag))
+        }
+        ["list"] | [] => {
+            let mut names = flags.0.iter().cloned().collect::<Vec<_>>();
+            names.sort();
+            Ok(format!("Flags: {}", names.join(", ")))
+        }
+        _ => Err("Expected set NAME, clear NAME or list".to_owned()),
+    }
+}
+
+pub struct DialogueGraphPlugin;
+
+impl Plugin for DialogueGraphPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<DialogueGraphs>()
+            .init_resource::<DialogueFlags>()
+            .init_resource::<DialogueRunner>()
+            .add_console_command(
+                "flag",
+                "flag set|clear NAME, flag list - edit dialogue flags",
+                flag_command,
+            )
+            .add_startup_system(load_dialogue_graphs_system.system())
+            .add_system(
+                start_conversation_system
+                    .system()
+                    .label("start_conversation")
+                    .after("interact")
+                    .before("dialogue_execution"),
+            )
+            .add_system(
+                conversation_system
+                    .system()
+                    .label("conversation")
+                    .after("dialogue_execution"),
+            )
+            .add_system(
+                choice_input_system
+                    .system()
+                    .label("choice_input")
+                    .after("conversation"),
+            );
+    }
+}
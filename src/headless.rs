@@ -0,0 +1,219 @@
+use crate::assets::{
+    add_silent_speech, santa_atlas, snowflake_atlas, AssetsReady, Portrait, SantaAssets,
+    SILENT_SPEECH, VOICED_SPEECH,
+};
+use crate::audio::SantaAudio;
+use crate::cli::CliArgs;
+use crate::console::Console;
+use crate::levels::LevelState;
+use crate::physics::Position;
+use crate::player::Santa;
+use crate::replay::{InputPlayback, InputRecorder, Recording};
+use crate::rng::GameRng;
+use crate::{SantaHeadlessPlugins, TIME_STEP};
+use bevy::asset::AssetPlugin;
+use bevy::input::keyboard::KeyboardInput;
+use bevy::input::{ElementState, InputPlugin};
+use bevy::prelude::*;
+use bevy::render::texture::{Extent3d, TextureDimension, TextureFormat};
+use bevy::window::ReceivedCharacter;
+
+fn blank_texture(width: u32, height: u32) -> Texture {
+    Texture::new_fill(
+        Extent3d::new(width, height, 1),
+        TextureDimension::D2,
+        &[0, 0, 0, 0],
+        TextureFormat::Rgba8UnormSrgb,
+    )
+}
+
+// Same layout as the loaded assets, but with blank textures and silent speech.
+fn stub_assets_system(
+    mut commands: Commands,
+    mut textures: ResMut<Assets<Texture>>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+) {
+    let santa = texture_atlases.add(santa_atlas(textures.add(blank_texture(111, 51))));
+    let snowflakes = texture_atlases.add(snowflake_atlas(textures.add(blank_texture(27, 13))));
+    let santa_portrait = Portrait {
+        atlas: santa.clone(),
+        index: 0,
+    };
+
+    let mut assets = SantaAssets {
+        santa: santa.clone(),
+        snowflakes,
+        ..Default::default()
+    };
+    assets
+        .portraits
+        .insert("santa".to_owned(), santa_portrait.clone());
+    assets.portraits.insert(
+        "elf".to_owned(),
+        Portrait {
+            atlas: santa,
+            index: 2,
+        },
+    );
+    for (name, text) in VOICED_SPEECH.iter().chain(SILENT_SPEECH) {
+        add_silent_speech(
+            &mut assets,
+            name,
+            text.to_string(),
+            Some(santa_portrait.clone()),
+        );
+    }
+
+    commands.insert_resource(assets);
+    commands.insert_resource(AssetsReady(true));
+}
+
+// Stands in for the window, renderer, audio and asset loading of `DefaultPlugins`.
+pub struct HeadlessPlugin {
+    pub seed: u64,
+}
+
+impl Plugin for HeadlessPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_plugin(AssetPlugin)
+            .add_plugin(InputPlugin)
+            .add_asset::<Texture>()
+            .add_asset::<TextureAtlas>()
+            .add_asset::<ColorMaterial>()
+            .add_asset::<Mesh>()
+            .add_asset::<Font>()
+            .add_asset::<AudioSource>()
+            .init_resource::<Windows>()
+            .add_event::<ReceivedCharacter>()
+            .insert_resource(GameRng::new(self.seed))
+            .insert_non_send_resource(SantaAudio::silent())
+            .add_startup_stage_before(
+                StartupStage::Startup,
+                "load_assets",
+                SystemStage::parallel()
+                    .with_system(stub_assets_system.system().label("load_assets")),
+            );
+    }
+}
+
+// Runs the game without a window. Gameplay systems advance by `TIME_STEP` per update, so a
+// step is one frame of game time however fast the host runs it.
+pub struct HeadlessGame {
+    pub app: App,
+}
+
+impl HeadlessGame {
+    pub fn new(seed: u64) -> Self {
+        Self::with_args(seed, CliArgs::default())
+    }
+
+    // Starts like the game launched with these command line arguments.
+    pub fn with_args(seed: u64, cli_args: CliArgs) -> Self {
+        let mut app_builder = App::build();
+        app_builder
+            .insert_resource(cli_args)
+            .add_plugins(MinimalPlugins)
+            .add_plugins(SantaHeadlessPlugins { seed });
+        Self {
+            app: app_builder.app,
+        }
+    }
+
+    // Feeds the recorded input instead of the keyboard, starting from the first frame.
+    pub fn replay(recording: Recording) -> Self {
+        let mut cli_args = CliArgs::default();
+        recording.apply_start_args(&mut cli_args);
+        let mut game = Self::with_args(recording.seed, cli_args);
+        game.app
+            .world
+            .insert_resource(InputPlayback::new(recording));
+        game
+    }
+
+    pub fn record(&mut self) {
+        let cli_args = self
+            .app
+            .world
+            .get_resource::<CliArgs>()
+            .cloned()
+            .unwrap_or_default();
+        self.app
+            .world
+            .insert_resource(InputRecorder::new(None, &cli_args));
+    }
+
+    pub fn recording(&self) -> Option<Recording> {
+        self.app
+            .world
+            .get_resource::<InputRecorder>()
+            .map(|recorder| recorder.recording.clone())
+    }
+
+    pub fn step(&mut self, frames: usize) {
+        for _ in 0..frames {
+            self.app.update();
+        }
+    }
+
+    pub fn step_seconds(&mut self, seconds: f32) {
+        self.step((seconds / TIME_STEP).round() as usize);
+    }
+
+    fn send_key(&mut self, key: KeyCode, state: ElementState) {
+        self.app
+            .world
+            .get_resource_mut::<Events<KeyboardInput>>()
+            .unwrap()
+            .send(KeyboardInput {
+                scan_code: 0,
+                key_code: Some(key),
+                state,
+            });
+    }
+
+    pub fn press(&mut self, key: KeyCode) {
+        self.send_key(key, ElementState::Pressed);
+    }
+
+    pub fn release(&mut self, key: KeyCode) {
+        self.send_key(key, ElementState::Released);
+    }
+
+    pub fn hold(&mut self, key: KeyCode, seconds: f32) {
+        self.press(key);
+        self.step_seconds(seconds);
+        self.release(key);
+        self.step(1);
+    }
+
+    // Runs a console command as if typed in, taking effect during the next frame.
+    pub fn run_command(&mut self, line: &str) {
+        self.app
+            .world
+            .get_resource_mut::<Console>()
+            .unwrap()
+            .submit(line);
+        self.step(1);
+    }
+
+    pub fn tap(&mut self, key: KeyCode) {
+        self.press(key);
+        self.step(1);
+        self.release(key);
+        self.step(1);
+    }
+
+    pub fn level(&self) -> LevelState {
+        self.app
+            .world
+            .get_resource::<State<LevelState>>()
+            .unwrap()
+            .current()
+            .clone()
+    }
+
+    pub fn santa_position(&mut self) -> Vec2 {
+        let mut query = self.app.world.query_filtered::<&Position, With<Santa>>();
+        query.iter(&self.app.world).next().unwrap().0
+    }
+}
@@ -0,0 +1,156 @@
+use crate::assets::SantaAssets;
+use crate::controls::{key_name, Action, ActionState, KeyBindings};
+use crate::levels::LevelState;
+use crate::physics::Position;
+use crate::player::Santa;
+use bevy::prelude::*;
+
+const PROMPT_OFFSET: f32 = 35.0;
+
+#[derive(Clone, Debug)]
+pub enum InteractionAction {
+    ChangeLevel {
+        level: LevelState,
+        spawn_point: Vec2,
+    },
+    Talk(String),
+}
+
+pub struct Interactable {
+    pub radius: f32,
+    pub prompt: String,
+    pub action: InteractionAction,
+}
+
+#[derive(Clone, Debug)]
+pub struct Interacted {
+    pub entity: Entity,
+    pub action: InteractionAction,
+}
+
+#[derive(Default)]
+pub struct ClosestInteractable(pub Option<Entity>);
+
+pub struct InteractionPrompt;
+
+fn init_interaction_prompt_system(mut commands: Commands, santa_assets: Res<SantaAssets>) {
+    commands
+        .spawn_bundle(Text2dBundle {
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: santa_assets.font.clone(),
+                    font_size: 8.0,
+                    color: Color::WHITE,
+                },
+                TextAlignment {
+                    vertical: VerticalAlign::Center,
+                    horizontal: HorizontalAlign::Center,
+                },
+            ),
+            transform: Transform::from_translation(Vec3::new(0.0, 0.0, 2.0)),
+            visible: Visible {
+                is_visible: false,
+                is_transparent: true,
+            },
+            ..Default::default()
+        })
+        .insert(InteractionPrompt);
+}
+
+fn find_closest_interactable_system(
+    mut closest_interactable: ResMut<ClosestInteractable>,
+    santa_query: Query<&Position, With<Santa>>,
+    interactable_query: Query<(Entity, &Position, &Interactable), Without<Santa>>,
+) {
+    closest_interactable.0 = santa_query.iter().next().and_then(|santa_position| {
+        interactable_query
+            .iter()
+            .map(|(entity, position, interactable)| {
+                (
+                    entity,
+                    position.0.distance(santa_position.0),
+                    interactable.radius,
+                )
+            })
+            .filter(|(_, distance, radius)| distance <= radius)
+            .min_by(|(_, a, _), (_, b, _)| a.partial_cmp(b).unwrap())
+            .map(|(entity, _, _)| entity)
+    });
+}
+
+fn update_interaction_prompt_system(
+    closest_interactable: Res<ClosestInteractable>,
+    key_bindings: Res<KeyBindings>,
+    interactable_query: Query<(&Position, &Interactable)>,
+    mut prompt_query: Query<(&mut Text, &mut Transform, &mut Visible), With<InteractionPrompt>>,
+) {
+    for (mut text, mut transform, mut visible) in prompt_query.iter_mut() {
+        match closest_interactable
+            .0
+            .and_then(|entity| interactable_query.get(entity).ok())
+        {
+            Some((position, interactable)) => {
+                text.sections[0].value = format!(
+                    "{} <{}>",
+                    interactable.prompt,
+                    key_name(key_bindings.action)
+                );
+                transform.translation.x = position.0.x;
+                transform.translation.y = position.0.y + PROMPT_OFFSET;
+                visible.is_visible = true;
+            }
+            None => {
+                visible.is_visible = false;
+            }
+        }
+    }
+}
+
+fn interact_system(
+    action_state: Res<ActionState>,
+    closest_interactable: Res<ClosestInteractable>,
+    interactable_query: Query<&Interactable>,
+    mut interacted_events: EventWriter<Interacted>,
+) {
+    if !action_state.just_released(Action::Interact) {
+        return;
+    }
+
+    if let Some(entity) = closest_interactable.0 {
+        if let Ok(interactable) = interactable_query.get(entity) {
+            interacted_events.send(Interacted {
+                entity,
+                action: interactable.action.clone(),
+            });
+        }
+    }
+}
+
+pub struct InteractionPlugin;
+
+impl Plugin for InteractionPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<ClosestInteractable>()
+            .add_event::<Interacted>()
+            .add_startup_system(init_interaction_prompt_system.system())
+            .add_system(
+                find_closest_interactable_system
+                    .system()
+                    .label("find_closest_interactable")
+                    .after("level_boundary"),
+            )
+            .add_system(
+                update_interaction_prompt_system
+                    .system()
+                    .label("update_interaction_prompt")
+                    .after("find_closest_interactable"),
+            )
+            .add_system(
+                interact_system
+                    .system()
+                    .label("interact")
+                    .after("find_closest_interactable"),
+            );
+    }
+}
@@ -1,8 +1,18 @@
-use crate::assets::SantaAssets;
+use crate::assets::{load_data, SantaAssets};
+use crate::cli::CliArgs;
+use crate::console::{parse_arg, ConsoleAppExt};
+use crate::interaction::{Interactable, Interacted, InteractionAction};
+use crate::lighting::{spawn_lights, GlowTexture};
+use crate::npc::{spawn_resident, NpcEvent, NpcState};
+use crate::particles::{spawn_emitters, ParticleData};
 use crate::physics::Position;
 use crate::player::Santa;
-use crate::snowflakes::init_snowflakes;
+use crate::render::ParallaxLayer;
+use crate::rng::GameRng;
+use crate::snowflakes::{init_snow_cover, init_snowflakes};
 use bevy::prelude::*;
+use serde_derive::Deserialize;
+use std::collections::HashMap;
 
 #[derive(StageLabel, Clone, Hash, Debug, Eq, PartialEq)]
 pub enum LevelState {
@@ -10,32 +20,275 @@ pub enum LevelState {
     Indoors,
 }
 
+impl LevelState {
+    pub fn key(&self) -> &'static str {
+        match self {
+            LevelState::Outside => "outside",
+            LevelState::Indoors => "indoors",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        match key {
+            "outside" => Some(LevelState::Outside),
+            "indoors" => Some(LevelState::Indoors),
+            _ => None,
+        }
+    }
+}
+
+fn default_parallax() -> f32 {
+    1.0
+}
+
+#[derive(Deserialize)]
+pub struct BackgroundLayer {
+    pub texture: String,
+    #[serde(default = "default_parallax")]
+    pub parallax: f32,
+    #[serde(default)]
+    pub depth: f32,
+}
+
+#[derive(Deserialize)]
+pub struct SnowSurface {
+    pub left: f32,
+    pub right: f32,
+    pub y: f32,
+}
+
+#[derive(Deserialize)]
+pub struct EmitterPlacement {
+    pub effect: String,
+    pub position: Vec2,
+    #[serde(default)]
+    pub depth: f32,
+}
+
+fn default_ambient() -> [f32; 3] {
+    [1.0, 1.0, 1.0]
+}
+
+#[derive(Clone, Copy, Deserialize)]
+pub struct Bounds {
+    pub left: f32,
+    pub right: f32,
+    pub bottom: f32,
+    pub top: f32,
+}
+
+impl Bounds {
+    pub fn rect(&self) -> Rect<f32> {
+        Rect {
+            left: self.left,
+            right: self.right,
+            bottom: self.bottom,
+            top: self.top,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct LightPlacement {
+    pub position: Vec2,
+    pub radius: f32,
+    pub color: [f32; 4],
+    #[serde(default)]
+    pub depth: f32,
+    #[serde(default)]
+    pub flicker: f32,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct LevelDefinition {
+    // Where Santa appears when the game starts in this level.
+    pub spawn: Vec2,
+    // Where Santa appears when coming in from the other level.
+    pub entry: Vec2,
+    pub player_boundary: Bounds,
+    pub camera_boundary: Bounds,
+    pub music: Option<String>,
+    pub ambience: Option<String>,
+    pub surface: Option<String>,
+    pub background: Vec<BackgroundLayer>,
+    pub snow: Vec<SnowSurface>,
+    pub emitters: Vec<EmitterPlacement>,
+    pub landing_effect: Option<String>,
+    pub ambient: [f32; 3],
+    pub lights: Vec<LightPlacement>,
+}
+
+impl Default for LevelDefinition {
+    fn default() -> Self {
+        Self {
+            spawn: Vec2::ZERO,
+            entry: Vec2::ZERO,
+            player_boundary: Bounds {
+                left: -105.0,
+                right: 105.0,
+                bottom: -97.0,
+                top: 105.0,
+            },
+            camera_boundary: Bounds {
+                left: -105.0,
+                right: 105.0,
+                bottom: -105.0,
+                top: 105.0,
+            },
+            music: None,
+            ambience: None,
+            surface: None,
+            background: Vec::new(),
+            snow: Vec::new(),
+            emitters: Vec::new(),
+            landing_effect: None,
+            ambient: default_ambient(),
+            lights: Vec::new(),
+        }
+    }
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct SurfaceSounds {
+    pub footsteps: Vec<String>,
+    pub jump: Vec<String>,
+    pub land: Vec<String>,
+}
+
+impl SurfaceSounds {
+    pub fn all(&self) -> impl Iterator<Item = &String> {
+        self.footsteps
+            .iter()
+            .chain(self.jump.iter())
+            .chain(self.land.iter())
+    }
+}
+
+#[derive(Default, Deserialize)]
+pub struct LevelData {
+    pub levels: HashMap<String, LevelDefinition>,
+    #[serde(default)]
+    pub surfaces: HashMap<String, SurfaceSounds>,
+    #[serde(skip)]
+    fallback: LevelDefinition,
+}
+
+impl LevelData {
+    pub fn get(&self, level: &LevelState) -> &LevelDefinition {
+        self.levels.get(level.key()).unwrap_or(&self.fallback)
+    }
+
+    pub fn surface(&self, level: &LevelState) -> Option<&SurfaceSounds> {
+        self.get(level)
+            .surface
+            .as_ref()
+            .and_then(|surface| self.surfaces.get(surface))
+    }
+}
+
 pub struct LevelPlayerBoundary(pub Rect<f32>);
 
 pub struct LevelCameraBoundary(pub Rect<f32>);
 
 pub struct SpawnPoint(pub Vec2);
 
-fn init_level_system(mut commands: Commands) {
-    commands.insert_resource(SpawnPoint(Vec2::new(-190.0, 0.0)));
+fn spawn_background(
+    parent: &mut ChildBuilder,
+    level: &LevelDefinition,
+    santa_assets: &SantaAssets,
+    materials: &mut Assets<ColorMaterial>,
+) {
+    for layer in &level.background {
+        let texture = match santa_assets.backgrounds.get(&layer.texture) {
+            Some(texture) => texture.clone(),
+            None => continue,
+        };
+        parent
+            .spawn_bundle(SpriteBundle {
+                material: materials.add(texture.into()),
+                transform: Transform::from_translation(Vec3::new(0.0, 0.0, layer.depth)),
+                ..Default::default()
+            })
+            .insert(ParallaxLayer(layer.parallax));
+    }
+}
+
+fn spawn_door(
+    parent: &mut ChildBuilder,
+    position: Vec2,
+    radius: f32,
+    prompt: &str,
+    action: InteractionAction,
+) {
+    parent
+        .spawn()
+        .insert(Position(position))
+        .insert(Interactable {
+            radius,
+            prompt: prompt.to_owned(),
+            action,
+        });
+}
+
+fn spawn_elf(parent: &mut ChildBuilder, santa_assets: &Res<SantaAssets>, position: Vec2) {
+    parent
+        .spawn_bundle(SpriteSheetBundle {
+            texture_atlas: santa_assets.santa.clone(),
+            sprite: TextureAtlasSprite {
+                color: Color::rgb(0.5, 1.0, 0.5),
+                index: 2,
+                ..Default::default()
+            },
+            transform: Transform {
+                translation: position.extend(0.9),
+                scale: Vec3::new(-0.7, 0.7, 1.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(Position(position))
+        .insert(Interactable {
+            radius: 25.0,
+            prompt: "Talk".to_owned(),
+            action: InteractionAction::Talk("elf".to_owned()),
+        });
+}
+
+fn requested_level_change(
+    interacted_events: &mut EventReader<Interacted>,
+) -> Option<(LevelState, Vec2)> {
+    interacted_events
+        .iter()
+        .filter_map(|interacted| match &interacted.action {
+            InteractionAction::ChangeLevel { level, spawn_point } => {
+                Some((level.clone(), *spawn_point))
+            }
+            InteractionAction::Talk(_) => None,
+        })
+        .last()
 }
 
 pub struct OutsideLevel;
 
+#[allow(clippy::too_many_arguments)]
 fn enter_outside_level_event(
     mut commands: Commands,
     santa_assets: Res<SantaAssets>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
+    level_data: Res<LevelData>,
+    particle_data: Res<ParticleData>,
+    glow_texture: Res<GlowTexture>,
     texture_atlases: Res<Assets<TextureAtlas>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut textures: ResMut<Assets<Texture>>,
     mut player_query: Query<&mut Position, With<Santa>>,
     spawn_point: Res<SpawnPoint>,
+    mut game_rng: ResMut<GameRng>,
 ) {
-    let level_camera_boundary = LevelCameraBoundary(Rect {
-        top: 105.0,
-        bottom: -105.0,
-        left: -270.0,
-        right: 270.0,
-    });
+    let level = level_data.get(&LevelState::Outside);
+    let level_camera_boundary = LevelCameraBoundary(level.camera_boundary.rect());
 
     commands
         .spawn()
@@ -43,27 +296,43 @@ fn enter_outside_level_event(
         .insert(GlobalTransform::default())
         .insert(Transform::default())
         .with_children(|parent| {
-            parent.spawn_bundle(SpriteBundle {
-                material: materials.add(
-                    texture_atlases
-                        .get(santa_assets.outside_background.clone())
-                        .unwrap()
-                        .texture
-                        .clone()
-                        .into(),
-                ),
-                transform: Transform::from_translation(Vec3::new(0.0, 0.0, 0.0)),
-                ..Default::default()
-            });
+            spawn_background(parent, level, &santa_assets, &mut materials);
+
+            spawn_elf(parent, &santa_assets, Vec2::new(-130.0, -79.0));
+
+            spawn_door(
+                parent,
+                Vec2::new(230.0, -72.0),
+                30.0,
+                "Enter",
+                InteractionAction::ChangeLevel {
+                    level: LevelState::Indoors,
+                    spawn_point: level_data.get(&LevelState::Indoors).entry,
+                },
+            );
 
-            init_snowflakes(parent, &level_camera_boundary, &santa_assets);
+            init_snowflakes(
+                parent,
+                &level_camera_boundary,
+                &santa_assets,
+                &texture_atlases,
+                &mut meshes,
+                &mut materials,
+                &mut game_rng,
+            );
+            init_snow_cover(parent, &level.snow, &mut textures, &mut materials);
+            spawn_lights(parent, &level.lights, &glow_texture, &mut materials);
+            spawn_emitters(
+                parent,
+                &level.emitters,
+                &particle_data,
+                &santa_assets,
+                &texture_atlases,
+                &mut meshes,
+                &mut materials,
+            );
         });
-    commands.insert_resource(LevelPlayerBoundary(Rect {
-        top: 105.0,
-        bottom: -97.0,
-        left: -270.0,
-        right: 270.0,
-    }));
+    commands.insert_resource(LevelPlayerBoundary(level.player_boundary.rect()));
     commands.insert_resource(level_camera_boundary);
     for mut position in player_query.iter_mut() {
         position.0 = spawn_point.0;
@@ -72,15 +341,12 @@ fn enter_outside_level_event(
 
 fn update_outside_level_event(
     mut state: ResMut<State<LevelState>>,
-    player_query: Query<&Position, With<Santa>>,
     mut spawn_point: ResMut<SpawnPoint>,
-    keyboard_input: Res<Input<KeyCode>>,
+    mut interacted_events: EventReader<Interacted>,
 ) {
-    for position in player_query.iter() {
-        if position.0.x >= 200.0 && keyboard_input.just_released(KeyCode::F) {
-            state.set(LevelState::Indoors).unwrap();
-            spawn_point.0 = Vec2::new(-80.0, -85.0);
-        }
+    if let Some((level, target_spawn_point)) = requested_level_change(&mut interacted_events) {
+        state.set(level).unwrap();
+        spawn_point.0 = target_spawn_point;
     }
 }
 
@@ -92,43 +358,59 @@ fn exit_outside_level_event(mut commands: Commands, query: Query<Entity, With<Ou
 
 pub struct IndoorsLevel;
 
+#[allow(clippy::too_many_arguments)]
 fn enter_indoors_level_event(
     mut commands: Commands,
     assets: Res<SantaAssets>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
+    level_data: Res<LevelData>,
+    particle_data: Res<ParticleData>,
+    glow_texture: Res<GlowTexture>,
     texture_atlases: Res<Assets<TextureAtlas>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
     mut player_query: Query<&mut Position, With<Santa>>,
     spawn_point: Res<SpawnPoint>,
 ) {
+    let level = level_data.get(&LevelState::Indoors);
     commands
         .spawn()
         .insert(IndoorsLevel)
+        .insert(GlobalTransform::default())
+        .insert(Transform::default())
         .with_children(|parent| {
-            parent.spawn_bundle(SpriteBundle {
-                material: materials.add(
-                    texture_atlases
-                        .get(assets.indoors_background.clone())
-                        .unwrap()
-                        .texture
-                        .clone()
-                        .into(),
-                ),
-                transform: Transform::from_translation(Vec3::new(0.0, 0.0, 0.0)),
-                ..Default::default()
-            });
+            spawn_background(parent, level, &assets, &mut materials);
+            spawn_lights(parent, &level.lights, &glow_texture, &mut materials);
+            spawn_emitters(
+                parent,
+                &level.emitters,
+                &particle_data,
+                &assets,
+                &texture_atlases,
+                &mut meshes,
+                &mut materials,
+            );
+
+            spawn_resident(
+                parent,
+                &assets,
+                Vec2::new(60.0, -72.0),
+                vec![80.0, -30.0],
+                NpcState::Sleeping,
+            );
+
+            spawn_door(
+                parent,
+                Vec2::new(-100.0, -72.0),
+                15.0,
+                "Leave",
+                InteractionAction::ChangeLevel {
+                    level: LevelState::Outside,
+                    spawn_point: level_data.get(&LevelState::Outside).entry,
+                },
+            );
         });
-    commands.insert_resource(LevelPlayerBoundary(Rect {
-        top: 105.0,
-        bottom: -97.0,
-        left: -105.0,
-        right: 105.0,
-    }));
-    commands.insert_resource(LevelCameraBoundary(Rect {
-        top: 105.0,
-        bottom: -105.0,
-        left: -105.0,
-        right: 105.0,
-    }));
+    commands.insert_resource(LevelPlayerBoundary(level.player_boundary.rect()));
+    commands.insert_resource(LevelCameraBoundary(level.camera_boundary.rect()));
     for mut position in player_query.iter_mut() {
         position.0 = spawn_point.0;
     }
@@ -136,15 +418,22 @@ fn enter_indoors_level_event(
 
 fn update_indoors_level_event(
     mut state: ResMut<State<LevelState>>,
-    player_query: Query<&Position, With<Santa>>,
+    level_data: Res<LevelData>,
     mut spawn_point: ResMut<SpawnPoint>,
-    keyboard_input: Res<Input<KeyCode>>,
+    mut interacted_events: EventReader<Interacted>,
+    mut npc_events: EventReader<NpcEvent>,
 ) {
-    for position in player_query.iter() {
-        if position.0.x <= -85.0 && keyboard_input.just_released(KeyCode::F) {
-            state.set(LevelState::Outside).unwrap();
-            spawn_point.0 = Vec2::new(195.0, -85.0);
-        }
+    let caught = npc_events
+        .iter()
+        .any(|npc_event| matches!(npc_event, NpcEvent::Caught(_)));
+
+    if caught {
+        state.set(LevelState::Outside).unwrap();
+        spawn_point.0 = level_data.get(&LevelState::Outside).entry;
+    } else if let Some((level, target_spawn_point)) = requested_level_change(&mut interacted_events)
+    {
+        state.set(level).unwrap();
+        spawn_point.0 = target_spawn_point;
     }
 }
 
@@ -154,6 +443,26 @@ fn exit_indoors_level_event(mut commands: Commands, query: Query<Entity, With<In
     }
 }
 
+fn level_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let level = args
+        .first()
+        .and_then(|key| LevelState::from_key(key))
+        .ok_or_else(|| "Expected outside or indoors".to_owned())?;
+    let spawn_point = if args.len() > 1 {
+        Vec2::new(parse_arg(args, 1, "x")?, parse_arg(args, 2, "y")?)
+    } else {
+        world.get_resource::<LevelData>().unwrap().get(&level).spawn
+    };
+
+    world
+        .get_resource_mut::<State<LevelState>>()
+        .unwrap()
+        .set(level.clone())
+        .map_err(|error| format!("{:?}", error))?;
+    world.get_resource_mut::<SpawnPoint>().unwrap().0 = spawn_point;
+    Ok(format!("Switching to {}", level.key()))
+}
+
 pub struct SantaLevelPlugin;
 
 #[derive(StageLabel, Clone, Debug, Eq, PartialEq, Hash)]
@@ -161,9 +470,26 @@ pub struct LevelStage;
 
 impl Plugin for SantaLevelPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_startup_system(init_level_system.system().label("init_level"))
+        let cli_args = app
+            .world()
+            .get_resource::<CliArgs>()
+            .cloned()
+            .unwrap_or_default();
+        let level_data = load_data::<LevelData>("levels.ron").unwrap_or_default();
+        let start_level = cli_args.level.unwrap_or(LevelState::Outside);
+        let spawn_point = cli_args
+            .spawn
+            .unwrap_or_else(|| level_data.get(&start_level).spawn);
+
+        app.insert_resource(level_data)
+            .insert_resource(SpawnPoint(spawn_point))
+            .add_console_command(
+                "level",
+                "level outside|indoors [X Y] - switch level",
+                level_command,
+            )
             .add_stage_before(CoreStage::Update, LevelStage, SystemStage::parallel())
-            .add_state_to_stage(LevelStage, LevelState::Outside)
+            .add_state_to_stage(LevelStage, start_level)
             .add_system_set_to_stage(
                 LevelStage,
                 SystemSet::on_enter(LevelState::Outside)
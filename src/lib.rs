@@ -0,0 +1,115 @@
+use crate::assets::SantaAssetPlugin;
+use crate::audio::SantaAudioPlugin;
+use crate::camera::SantaCameraPlugin;
+use crate::config::SantaConfigPlugin;
+use crate::console::ConsolePlugin;
+use crate::controls::SantaControlsPlugin;
+use crate::debug::DebugOverlayPlugin;
+use crate::dialogue::DialoguePlugin;
+use crate::dialogue_graph::DialogueGraphPlugin;
+use crate::headless::HeadlessPlugin;
+use crate::interaction::InteractionPlugin;
+use crate::levels::SantaLevelPlugin;
+use crate::lighting::LightingPlugin;
+use crate::logging::CrashReportPlugin;
+use crate::npc::NpcPlugin;
+use crate::particles::ParticlesPlugin;
+use crate::physics::SantaPhysicsPlugin;
+use crate::player::SantaPlayerPlugin;
+use crate::render::SantaRenderPlugin;
+use crate::replay::ReplayPlugin;
+use crate::rng::SantaRngPlugin;
+use crate::sfx::SantaSfxPlugin;
+use crate::snowflakes::SnowflakesPlugin;
+use crate::weather::WeatherPlugin;
+use bevy::app::PluginGroupBuilder;
+use bevy::prelude::*;
+
+#[macro_use]
+extern crate lazy_static;
+
+pub mod assets;
+pub mod audio;
+pub mod camera;
+pub mod cli;
+pub mod config;
+pub mod console;
+pub mod controls;
+pub mod debug;
+pub mod dialogue;
+pub mod dialogue_graph;
+pub mod headless;
+pub mod interaction;
+pub mod levels;
+pub mod lighting;
+pub mod logging;
+pub mod npc;
+pub mod particles;
+pub mod physics;
+pub mod player;
+pub mod render;
+pub mod replay;
+pub mod rng;
+pub mod sfx;
+pub mod snowflakes;
+pub mod weather;
+
+pub const TIME_STEP: f32 = 1.0 / 60.0;
+
+pub struct SantaGamePlugins;
+
+impl PluginGroup for SantaGamePlugins {
+    fn build(&mut self, group: &mut PluginGroupBuilder) {
+        group
+            .add(SantaConfigPlugin)
+            .add(ReplayPlugin)
+            .add(SantaRngPlugin)
+            .add(SantaAssetPlugin)
+            .add(SantaAudioPlugin)
+            .add(SantaCameraPlugin)
+            .add(SantaControlsPlugin)
+            .add(SantaLevelPlugin)
+            .add(SantaPlayerPlugin)
+            .add(SantaPhysicsPlugin)
+            .add(NpcPlugin)
+            .add(SantaRenderPlugin)
+            .add(DialoguePlugin)
+            .add(DialogueGraphPlugin)
+            .add(InteractionPlugin)
+            .add(SantaSfxPlugin)
+            .add(WeatherPlugin)
+            .add(SnowflakesPlugin)
+            .add(ParticlesPlugin)
+            .add(LightingPlugin)
+            .add(DebugOverlayPlugin)
+            .add(ConsolePlugin)
+            .add(CrashReportPlugin);
+    }
+}
+
+// The game logic without window, renderer or audio, to run on top of `MinimalPlugins`.
+pub struct SantaHeadlessPlugins {
+    pub seed: u64,
+}
+
+impl PluginGroup for SantaHeadlessPlugins {
+    fn build(&mut self, group: &mut PluginGroupBuilder) {
+        group
+            .add(HeadlessPlugin { seed: self.seed })
+            .add(ReplayPlugin)
+            .add(SantaControlsPlugin)
+            .add(SantaLevelPlugin)
+            .add(SantaPlayerPlugin)
+            .add(SantaPhysicsPlugin)
+            .add(NpcPlugin)
+            .add(SantaRenderPlugin)
+            .add(DialoguePlugin)
+            .add(DialogueGraphPlugin)
+            .add(InteractionPlugin)
+            .add(WeatherPlugin)
+            .add(SnowflakesPlugin)
+            .add(ParticlesPlugin)
+            .add(LightingPlugin)
+            .add(ConsolePlugin);
+    }
+}
@@ -0,0 +1,308 @@
+use crate::assets::load_data;
+use crate::dialogue::DialogueQueue;
+use crate::levels::{LevelData, LevelState, LightPlacement};
+use crate::rng::GameRng;
+use crate::TIME_STEP;
+use bevy::asset::HandleId;
+use bevy::prelude::*;
+use bevy::render::texture::{Extent3d, TextureDimension, TextureFormat};
+use rand::distributions::{Distribution, Uniform};
+use serde_derive::Deserialize;
+use std::collections::HashMap;
+
+const RNG_STREAM: &str = "lighting";
+const GLOW_TEXTURE_SIZE: u32 = 64;
+const FLICKER_SMOOTHING: f32 = 0.2;
+// How much brighter lights appear when the scene around them is dark.
+const GLOW_CONTRAST: f32 = 0.6;
+// The tint moves in steps this small, so tinted materials are not rewritten every frame.
+const TINT_STEPS: f32 = 64.0;
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct LightingData {
+    pub night_length: f32,
+    // Tint at a point of the night, from 0.0 at dusk to 1.0 at dawn.
+    pub keyframes: Vec<(f32, [f32; 3])>,
+}
+
+impl Default for LightingData {
+    fn default() -> Self {
+        Self {
+            night_length: 600.0,
+            keyframes: Vec::new(),
+        }
+    }
+}
+
+impl LightingData {
+    fn night_tint(&self, progress: f32) -> [f32; 3] {
+        let next = self
+            .keyframes
+            .iter()
+            .position(|(time, _)| *time > progress)
+            .unwrap_or(self.keyframes.len());
+        match (next.checked_sub(1), self.keyframes.get(next)) {
+            (Some(previous), Some((to_time, to))) => {
+                let (from_time, from) = self.keyframes[previous];
+                let t = (progress - from_time) / (to_time - from_time).max(f32::EPSILON);
+                [
+                    from[0] + (to[0] - from[0]) * t,
+                    from[1] + (to[1] - from[1]) * t,
+                    from[2] + (to[2] - from[2]) * t,
+                ]
+            }
+            (None, Some((_, first))) => *first,
+            (Some(last), None) => self.keyframes[last].1,
+            (None, None) => [1.0, 1.0, 1.0],
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct NightClock {
+    pub elapsed: f32,
+}
+
+impl NightClock {
+    pub fn progress(&self, lighting_data: &LightingData) -> f32 {
+        (self.elapsed / lighting_data.night_length.max(f32::EPSILON)).min(1.0)
+    }
+
+    pub fn is_over(&self, lighting_data: &LightingData) -> bool {
+        self.progress(lighting_data) >= 1.0
+    }
+}
+
+pub struct Lighting {
+    pub tint: Color,
+}
+
+impl Default for Lighting {
+    fn default() -> Self {
+        Self { tint: Color::WHITE }
+    }
+}
+
+impl Lighting {
+    fn brightness(&self) -> f32 {
+        (self.tint.r() + self.tint.g() + self.tint.b()) / 3.0
+    }
+}
+
+// Entities that keep their own colors regardless of the lighting, like glow sprites.
+pub struct Unlit;
+
+// Untinted color of an atlas sprite; systems that recolor sprites should write this instead.
+pub struct BaseColor(pub Color);
+
+pub struct GlowLight {
+    pub color: Color,
+    pub flicker: f32,
+    current: f32,
+}
+
+pub struct GlowTexture(pub Handle<Texture>);
+
+#[derive(Default)]
+struct MaterialColors(HashMap<HandleId, Color>);
+
+fn quantize(value: f32) -> f32 {
+    (value * TINT_STEPS).round() / TINT_STEPS
+}
+
+fn multiply(color: Color, tint: Color) -> Color {
+    Color::rgba(
+        color.r() * tint.r(),
+        color.g() * tint.g(),
+        color.b() * tint.b(),
+        color.a(),
+    )
+}
+
+fn glow_texture_data() -> Vec<u8> {
+    let half = GLOW_TEXTURE_SIZE as f32 / 2.0;
+    let mut data = Vec::with_capacity((GLOW_TEXTURE_SIZE * GLOW_TEXTURE_SIZE * 4) as usize);
+    for y in 0..GLOW_TEXTURE_SIZE {
+        for x in 0..GLOW_TEXTURE_SIZE {
+            let offset = Vec2::new(x as f32 + 0.5 - half, y as f32 + 0.5 - half) / half;
+            let falloff = (1.0 - offset.length()).max(0.0);
+            data.extend_from_slice(&[255, 255, 255, (falloff * falloff * 255.0) as u8]);
+        }
+    }
+    data
+}
+
+fn init_lighting_system(mut commands: Commands, mut textures: ResMut<Assets<Texture>>) {
+    let texture = Texture::new(
+        Extent3d::new(GLOW_TEXTURE_SIZE, GLOW_TEXTURE_SIZE, 1),
+        TextureDimension::D2,
+        glow_texture_data(),
+        TextureFormat::Rgba8UnormSrgb,
+    );
+    commands.insert_resource(GlowTexture(textures.add(texture)));
+}
+
+pub fn spawn_lights(
+    parent: &mut ChildBuilder,
+    lights: &[LightPlacement],
+    glow_texture: &GlowTexture,
+    materials: &mut Assets<ColorMaterial>,
+) {
+    for light in lights {
+        let [red, green, blue, alpha] = light.color;
+        let color = Color::rgba(red, green, blue, alpha);
+        parent
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite::new(Vec2::splat(light.radius * 2.0)),
+                material: materials.add(ColorMaterial {
+                    color,
+                    texture: Some(glow_texture.0.clone()),
+                }),
+                transform: Transform::from_translation(light.position.extend(light.depth)),
+                ..Default::default()
+            })
+            .insert(GlowLight {
+                color,
+                flicker: light.flicker,
+                current: 1.0,
+            })
+            .insert(Unlit);
+    }
+}
+
+fn night_clock_system(mut night_clock: ResMut<NightClock>) {
+    night_clock.elapsed += TIME_STEP;
+}
+
+fn update_lighting_system(
+    state: Res<State<LevelState>>,
+    level_data: Res<LevelData>,
+    lighting_data: Res<LightingData>,
+    night_clock: Res<NightClock>,
+    mut lighting: ResMut<Lighting>,
+) {
+    let [night_red, night_green, night_blue] =
+        lighting_data.night_tint(night_clock.progress(&lighting_data));
+    let [ambient_red, ambient_green, ambient_blue] = level_data.get(state.current()).ambient;
+    let tint = Color::rgb(
+        quantize(night_red * ambient_red),
+        quantize(night_green * ambient_green),
+        quantize(night_blue * ambient_blue),
+    );
+    if lighting.tint != tint {
+        lighting.tint = tint;
+    }
+}
+
+fn dawn_system(
+    lighting_data: Res<LightingData>,
+    night_clock: Res<NightClock>,
+    mut dialogue_queue: ResMut<DialogueQueue>,
+    mut announced: Local<bool>,
+) {
+    if !*announced && night_clock.is_over(&lighting_data) {
+        *announced = true;
+        dialogue_queue.push("dawn_1");
+    }
+}
+
+fn tint_materials_system(
+    lighting: Res<Lighting>,
+    mut material_colors: ResMut<MaterialColors>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    material_query: Query<&Handle<ColorMaterial>, (Without<Node>, Without<Unlit>)>,
+) {
+    // Materials can be shared, so their untinted colors are kept per material, not per entity.
+    material_colors
+        .0
+        .retain(|id, _| materials.get(*id).is_some());
+
+    for handle in material_query.iter() {
+        let color = match materials.get(handle) {
+            Some(material) => material.color,
+            None => continue,
+        };
+        let base = *material_colors.0.entry(handle.id).or_insert(color);
+        let tinted = multiply(base, lighting.tint);
+        if color != tinted {
+            if let Some(material) = materials.get_mut(handle) {
+                material.color = tinted;
+            }
+        }
+    }
+}
+
+fn tint_sprites_system(
+    mut commands: Commands,
+    lighting: Res<Lighting>,
+    mut sprite_query: Query<(&BaseColor, &mut TextureAtlasSprite), Without<Unlit>>,
+    new_sprite_query: Query<(Entity, &TextureAtlasSprite), (Without<BaseColor>, Without<Unlit>)>,
+) {
+    for (entity, sprite) in new_sprite_query.iter() {
+        commands.entity(entity).insert(BaseColor(sprite.color));
+    }
+
+    for (base_color, mut sprite) in sprite_query.iter_mut() {
+        let tinted = multiply(base_color.0, lighting.tint);
+        if sprite.color != tinted {
+            sprite.color = tinted;
+        }
+    }
+}
+
+fn glow_system(
+    lighting: Res<Lighting>,
+    mut game_rng: ResMut<GameRng>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut glow_query: Query<(&mut GlowLight, &Handle<ColorMaterial>)>,
+) {
+    let rng = game_rng.stream(RNG_STREAM);
+    let contrast = 1.0 - lighting.brightness() * GLOW_CONTRAST;
+
+    for (mut glow_light, handle) in glow_query.iter_mut() {
+        let target = 1.0 - glow_light.flicker * Uniform::new_inclusive(0.0, 1.0).sample(rng);
+        glow_light.current += (target - glow_light.current) * FLICKER_SMOOTHING;
+
+        if let Some(material) = materials.get_mut(handle) {
+            let color = glow_light.color;
+            material
+                .color
+                .set_a(color.a() * glow_light.current * contrast);
+        }
+    }
+}
+
+pub struct LightingPlugin;
+
+impl Plugin for LightingPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(load_data::<LightingData>("lighting.ron").unwrap_or_default())
+            .init_resource::<NightClock>()
+            .init_resource::<Lighting>()
+            .init_resource::<MaterialColors>()
+            .add_startup_system(init_lighting_system.system())
+            .add_system(night_clock_system.system().label("night_clock"))
+            .add_system(
+                update_lighting_system
+                    .system()
+                    .label("update_lighting")
+                    .after("night_clock"),
+            )
+            .add_system(dawn_system.system().label("dawn").after("night_clock"))
+            .add_system(
+                tint_materials_system
+                    .system()
+                    .label("tint_materials")
+                    .after("update_lighting"),
+            )
+            .add_system(
+                tint_sprites_system
+                    .system()
+                    .label("tint_sprites")
+                    .after("update_lighting")
+                    .after("animate_npc"),
+            )
+            .add_system(glow_system.system().label("glow").after("update_lighting"));
+    }
+}
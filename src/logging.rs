@@ -0,0 +1,287 @@
+use crate::config::user_dir;
+use crate::dialogue::DialogueState;
+use crate::levels::LevelState;
+use crate::physics::Position;
+use crate::player::Santa;
+use crate::rng::GameRng;
+use bevy::prelude::*;
+use log::{LevelFilter, Log, Metadata, Record};
+use simplelog::{
+    ColorChoice, CombinedLogger, Config, SharedLogger, TermLogger, TerminalMode, WriteLogger,
+};
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::panic::PanicInfo;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, TryLockError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const LOG_DIR: &str = "logs";
+const CRASH_DIR: &str = "crashes";
+const LOG_PREFIX: &str = "santa-game-";
+const CRASH_PREFIX: &str = "crash-";
+const MAX_NAME_SUFFIX: u32 = 100;
+const KEEP_LOG_FILES: usize = 5;
+const RECENT_LOG_LINES: usize = 100;
+
+#[derive(Default)]
+struct CrashContext {
+    level: Option<String>,
+    santa_position: Option<Vec2>,
+    dialogue_state: Option<String>,
+    seed: Option<u64>,
+    seconds_since_startup: f64,
+}
+
+lazy_static! {
+    static ref RECENT_LINES: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+    // The panic hook cannot reach the world, so the game state it reports is copied here
+    // every frame.
+    static ref CRASH_CONTEXT: Mutex<CrashContext> = Mutex::new(CrashContext::default());
+}
+
+// Never blocks, so a panic while a lock is held does not hang the panic hook.
+fn try_lock<T>(mutex: &Mutex<T>) -> Option<MutexGuard<T>> {
+    match mutex.try_lock() {
+        Ok(guard) => Some(guard),
+        Err(TryLockError::Poisoned(error)) => Some(error.into_inner()),
+        Err(TryLockError::WouldBlock) => None,
+    }
+}
+
+fn timestamp() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis())
+}
+
+// Creates `{prefix}{millis}.{extension}`, adding `-1`, `-2`, ... when a file of the same name
+// already exists, so two runs never share a file.
+fn create_unique_file(
+    dir: &Path,
+    prefix: &str,
+    extension: &str,
+) -> Result<(PathBuf, File), String> {
+    let timestamp = timestamp();
+    for suffix in 0..MAX_NAME_SUFFIX {
+        let name = match suffix {
+            0 => format!("{}{}.{}", prefix, timestamp, extension),
+            _ => format!("{}{}-{}.{}", prefix, timestamp, suffix, extension),
+        };
+        let path = dir.join(name);
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(error) if error.kind() == ErrorKind::AlreadyExists => continue,
+            Err(error) => return Err(error.to_string()),
+        }
+    }
+    Err(format!(
+        "Too many files named {}{} in {:?}",
+        prefix, timestamp, dir
+    ))
+}
+
+// Timestamp and suffix of a log file name, the order the files were created in.
+fn log_file_order(name: &str) -> Option<(u128, u32)> {
+    let stem = name.strip_prefix(LOG_PREFIX)?.strip_suffix(".log")?;
+    let mut parts = stem.splitn(2, '-');
+    let timestamp = parts.next()?.parse().ok()?;
+    let suffix = match parts.next() {
+        Some(suffix) => suffix.parse().ok()?,
+        None => 0,
+    };
+    Some((timestamp, suffix))
+}
+
+// Keeps the last log lines in memory for crash reports.
+struct RecentLogger {
+    level: LevelFilter,
+}
+
+impl Log for RecentLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        if let Some(mut lines) = try_lock(&RECENT_LINES) {
+            lines.push_back(format!(
+                "[{}] {}: {}",
+                record.level(),
+                record.target(),
+                record.args()
+            ));
+            while lines.len() > RECENT_LOG_LINES {
+                lines.pop_front();
+            }
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+impl SharedLogger for RecentLogger {
+    fn level(&self) -> LevelFilter {
+        self.level
+    }
+
+    fn config(&self) -> Option<&Config> {
+        None
+    }
+
+    fn as_log(self: Box<Self>) -> Box<dyn Log> {
+        Box::new(*self)
+    }
+}
+
+// Removes the oldest log files so at most `keep` remain.
+fn rotate_log_files(log_dir: &Path, keep: usize) {
+    let entries = match std::fs::read_dir(log_dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    let mut log_files = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter_map(|path| {
+            let order = log_file_order(path.file_name()?.to_str()?)?;
+            Some((order, path))
+        })
+        .collect::<Vec<_>>();
+    log_files.sort();
+
+    let excess = log_files.len().saturating_sub(keep);
+    for (_, path) in log_files.drain(..excess) {
+        if let Err(error) = std::fs::remove_file(&path) {
+            eprintln!("Could not remove old log file {:?}: {}", path, error);
+        }
+    }
+}
+
+fn create_log_file() -> Result<(PathBuf, File), String> {
+    let log_dir = user_dir()
+        .ok_or_else(|| "No user directory".to_owned())?
+        .join(LOG_DIR);
+    std::fs::create_dir_all(&log_dir).map_err(|error| error.to_string())?;
+    // Make room for the new file.
+    rotate_log_files(&log_dir, KEEP_LOG_FILES - 1);
+
+    create_unique_file(&log_dir, LOG_PREFIX, "log")
+}
+
+fn write_crash_report(panic_info: &PanicInfo) -> Result<PathBuf, String> {
+    let mut report = format!(
+        "Santa game {} crash report\n\nPanic: {}\n",
+        env!("CARGO_PKG_VERSION"),
+        panic_info
+    );
+
+    match try_lock(&CRASH_CONTEXT) {
+        Some(context) => {
+            let unknown = || "unknown".to_owned();
+            let position = context.santa_position.map_or_else(unknown, |position| {
+                format!("({:.1}, {:.1})", position.x, position.y)
+            });
+            report += &format!("Level: {}\n", context.level.clone().unwrap_or_else(unknown));
+            report += &format!("Santa position: {}\n", position);
+            report += &format!(
+                "Dialogue state: {}\n",
+                context.dialogue_state.clone().unwrap_or_else(unknown)
+            );
+            report += &format!(
+                "Seed: {}\n",
+                context.seed.map_or_else(unknown, |seed| seed.to_string())
+            );
+            report += &format!("Running for: {:.1}s\n", context.seconds_since_startup);
+        }
+        None => report += "Game state unavailable\n",
+    }
+
+    report += "\nRecent log lines:\n";
+    if let Some(lines) = try_lock(&RECENT_LINES) {
+        for line in lines.iter() {
+            report += line;
+            report += "\n";
+        }
+    }
+
+    let crash_dir = user_dir()
+        .ok_or_else(|| "No user directory".to_owned())?
+        .join(CRASH_DIR);
+    std::fs::create_dir_all(&crash_dir).map_err(|error| error.to_string())?;
+    let (path, mut file) = create_unique_file(&crash_dir, CRASH_PREFIX, "txt")?;
+    file.write_all(report.as_bytes())
+        .map_err(|error| error.to_string())?;
+    Ok(path)
+}
+
+fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |panic_info| {
+        default_hook(panic_info);
+        match write_crash_report(panic_info) {
+            Ok(path) => {
+                error!("Wrote crash report to {:?}", path);
+                eprintln!(
+                    "\nSanta crashed, sorry! A crash report was saved to\n  {}\n\
+                     Please attach it when reporting the problem.",
+                    path.display()
+                );
+            }
+            Err(error) => eprintln!("Could not write a crash report: {}", error),
+        }
+    }));
+}
+
+// Logs to the terminal, a rotating file in the user directory and the memory kept for crash
+// reports, and installs the panic hook writing those reports.
+pub fn init_logging(level: LevelFilter) {
+    let mut loggers: Vec<Box<dyn SharedLogger>> = vec![
+        TermLogger::new(
+            level,
+            Config::default(),
+            TerminalMode::Mixed,
+            ColorChoice::Always,
+        ),
+        Box::new(RecentLogger { level }),
+    ];
+    let log_path = create_log_file().map(|(path, file)| {
+        loggers.push(WriteLogger::new(level, Config::default(), file));
+        path
+    });
+    CombinedLogger::init(loggers).unwrap();
+
+    match log_path {
+        Ok(path) => info!("Logging to {:?}", path),
+        Err(error) => warn!("Not logging to a file: {}", error),
+    }
+    install_panic_hook();
+}
+
+fn update_crash_context_system(
+    time: Res<Time>,
+    state: Option<Res<State<LevelState>>>,
+    dialogue_state: Option<Res<DialogueState>>,
+    game_rng: Option<Res<GameRng>>,
+    santa_query: Query<&Position, With<Santa>>,
+) {
+    if let Some(mut context) = try_lock(&CRASH_CONTEXT) {
+        context.level = state.map(|state| state.current().key().to_owned());
+        context.santa_position = santa_query.iter().next().map(|position| position.0);
+        context.dialogue_state =
+            dialogue_state.map(|dialogue_state| format!("{:?}", *dialogue_state));
+        context.seed = game_rng.map(|game_rng| game_rng.seed());
+        context.seconds_since_startup = time.seconds_since_startup();
+    }
+}
+
+pub struct CrashReportPlugin;
+
+impl Plugin for CrashReportPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_to_stage(CoreStage::Last, update_crash_context_system.system());
+    }
+}
@@ -1,49 +1,41 @@
-use crate::assets::SantaAssetPlugin;
-use crate::camera::SantaCameraPlugin;
-use crate::dialogue::DialoguePlugin;
-use crate::levels::SantaLevelPlugin;
-use crate::physics::SantaPhysicsPlugin;
-use crate::player::SantaPlayerPlugin;
-use crate::render::SantaRenderPlugin;
-use crate::snowflakes::SnowflakesPlugin;
+use bevy::audio::AudioPlugin;
 use bevy::diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin};
+use bevy::log::LogPlugin;
 use bevy::prelude::*;
-use log::LevelFilter;
-use simplelog::{ColorChoice, Config, TermLogger, TerminalMode};
-
-#[macro_use]
-extern crate lazy_static;
-
-mod assets;
-mod camera;
-mod dialogue;
-mod levels;
-mod physics;
-mod player;
-mod render;
-mod snowflakes;
-
-const TIME_STEP: f32 = 1.0 / 60.0;
+use santa_game::cli::{CliArgs, USAGE};
+use santa_game::logging::init_logging;
+use santa_game::SantaGamePlugins;
 
 fn main() {
-    TermLogger::init(
-        LevelFilter::Info,
-        Config::default(),
-        TerminalMode::Mixed,
-        ColorChoice::Always,
-    )
-    .unwrap();
+    let cli_args = match CliArgs::from_env() {
+        Ok(cli_args) => cli_args,
+        Err(error) => {
+            eprintln!("{}\n\n{}", error, USAGE);
+            std::process::exit(2);
+        }
+    };
+    if cli_args.help {
+        println!("{}", USAGE);
+        return;
+    }
+
+    init_logging(cli_args.log_level);
 
-    App::build()
-        .add_plugins(DefaultPlugins)
-        .add_plugin(SantaAssetPlugin)
-        .add_plugin(SantaCameraPlugin)
-        .add_plugin(SantaLevelPlugin)
-        .add_plugin(SantaPlayerPlugin)
-        .add_plugin(SantaPhysicsPlugin)
-        .add_plugin(SantaRenderPlugin)
-        .add_plugin(DialoguePlugin)
-        .add_plugin(SnowflakesPlugin)
+    let mut app = App::build();
+    if let Some((width, height)) = cli_args.windowed {
+        app.insert_resource(WindowDescriptor {
+            width,
+            height,
+            ..Default::default()
+        });
+    }
+    // Bevy's log macros reach `log` through tracing's log feature once its own subscriber is gone,
+    // and `SantaAudio` holds the only audio output.
+    app.insert_resource(cli_args)
+        .add_plugins_with(DefaultPlugins, |group| {
+            group.disable::<LogPlugin>().disable::<AudioPlugin>()
+        })
+        .add_plugins(SantaGamePlugins)
         .add_plugin(LogDiagnosticsPlugin::default())
         .add_plugin(FrameTimeDiagnosticsPlugin::default())
         .run();
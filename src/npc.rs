@@ -0,0 +1,262 @@
+use crate::assets::SantaAssets;
+use crate::lighting::BaseColor;
+use crate::physics::{Gravity, GroundState, Position, Speed, SpriteBoundary};
+use crate::player::{AnimationTimer, Santa};
+use crate::TIME_STEP;
+use bevy::prelude::*;
+
+const NPC_WALK_SPEED: f32 = 25.0;
+const WAYPOINT_TOLERANCE: f32 = 1.0;
+const HEARING_RANGE: f32 = 60.0;
+const CATCH_TIME: f32 = 1.5;
+const CALM_DOWN_TIME: f32 = 3.0;
+
+pub struct Npc;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NpcState {
+    Sleeping,
+    Awake,
+    Alert,
+}
+
+pub struct PatrolPath {
+    pub waypoints: Vec<f32>,
+    pub current: usize,
+}
+
+pub struct Vision {
+    pub range: f32,
+    pub half_angle: f32,
+}
+
+#[derive(Default)]
+pub struct Suspicion {
+    pub seen_secs: f32,
+    pub unseen_secs: f32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum NpcEvent {
+    WokeUp(Entity),
+    Spotted(Entity),
+    LostSight(Entity),
+    Caught(Entity),
+}
+
+pub fn spawn_resident(
+    parent: &mut ChildBuilder,
+    santa_assets: &Res<SantaAssets>,
+    position: Vec2,
+    waypoints: Vec<f32>,
+    state: NpcState,
+) {
+    parent
+        .spawn()
+        .insert(Npc)
+        .insert_bundle(SpriteSheetBundle {
+            texture_atlas: santa_assets.santa.clone(),
+            transform: Transform::from_translation(position.extend(0.9)),
+            ..Default::default()
+        })
+        .insert(BaseColor(Color::rgb(0.6, 0.7, 1.0)))
+        .insert(AnimationTimer(Timer::from_seconds(0.3, true)))
+        .insert(Position(position))
+        .insert(Speed::default())
+        .insert(SpriteBoundary(Rect {
+            left: -15.0,
+            right: 15.0,
+            top: 25.0,
+            bottom: -25.0,
+        }))
+        .insert(Gravity)
+        .insert(GroundState::default())
+        .insert(PatrolPath {
+            waypoints,
+            current: 0,
+        })
+        .insert(Vision {
+            range: 70.0,
+            half_angle: std::f32::consts::FRAC_PI_4,
+        })
+        .insert(Suspicion::default())
+        .insert(state);
+}
+
+fn can_see(npc_position: Vec2, facing: f32, vision: &Vision, target: Vec2) -> bool {
+    let to_target = target - npc_position;
+    let distance = to_target.length();
+    if distance > vision.range {
+        return false;
+    }
+    if distance < f32::EPSILON {
+        return true;
+    }
+
+    let facing = Vec2::new(facing.signum(), 0.0);
+    facing.angle_between(to_target).abs() <= vision.half_angle
+}
+
+fn npc_senses_system(
+    mut npc_events: EventWriter<NpcEvent>,
+    santa_query: Query<(&Position, &GroundState), With<Santa>>,
+    mut npc_query: Query<
+        (
+            Entity,
+            &Position,
+            &Transform,
+            &Vision,
+            &mut NpcState,
+            &mut Suspicion,
+        ),
+        (With<Npc>, Without<Santa>),
+    >,
+) {
+    let (santa_position, santa_ground_state) = match santa_query.iter().next() {
+        Some(santa) => santa,
+        None => return,
+    };
+
+    for (entity, position, transform, vision, mut state, mut suspicion) in npc_query.iter_mut() {
+        let sees_santa = can_see(position.0, transform.scale.x, vision, santa_position.0);
+
+        match *state {
+            NpcState::Sleeping => {
+                if santa_ground_state.just_landed
+                    && position.0.distance(santa_position.0) <= HEARING_RANGE
+                {
+                    *state = NpcState::Awake;
+                    npc_events.send(NpcEvent::WokeUp(entity));
+                }
+            }
+
+            NpcState::Awake => {
+                if sees_santa {
+                    *state = NpcState::Alert;
+                    *suspicion = Suspicion::default();
+                    npc_events.send(NpcEvent::Spotted(entity));
+                }
+            }
+
+            NpcState::Alert => {
+                if sees_santa {
+                    suspicion.seen_secs += TIME_STEP;
+                    suspicion.unseen_secs = 0.0;
+                    if suspicion.seen_secs >= CATCH_TIME {
+                        *state = NpcState::Awake;
+                        *suspicion = Suspicion::default();
+                        npc_events.send(NpcEvent::Caught(entity));
+                    }
+                } else {
+                    suspicion.unseen_secs += TIME_STEP;
+                    if suspicion.unseen_secs >= CALM_DOWN_TIME {
+                        *state = NpcState::Awake;
+                        *suspicion = Suspicion::default();
+                        npc_events.send(NpcEvent::LostSight(entity));
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn npc_patrol_system(
+    mut npc_query: Query<(&Position, &mut Speed, &mut PatrolPath, &NpcState), With<Npc>>,
+) {
+    for (position, mut speed, mut patrol_path, state) in npc_query.iter_mut() {
+        speed.0.x = match state {
+            NpcState::Sleeping => 0.0,
+            NpcState::Awake => {
+                if patrol_path.waypoints.is_empty() {
+                    0.0
+                } else {
+                    let mut target = patrol_path.waypoints[patrol_path.current];
+                    if (target - position.0.x).abs() <= WAYPOINT_TOLERANCE {
+                        patrol_path.current =
+                            (patrol_path.current + 1) % patrol_path.waypoints.len();
+                        target = patrol_path.waypoints[patrol_path.current];
+                    }
+                    (target - position.0.x).signum() * NPC_WALK_SPEED
+                }
+            }
+            NpcState::Alert => 0.0,
+        };
+    }
+}
+
+fn animate_npc_system(
+    time: Res<Time>,
+    santa_query: Query<&Position, With<Santa>>,
+    mut query: Query<
+        (
+            &mut Transform,
+            &Position,
+            &Speed,
+            &NpcState,
+            &mut AnimationTimer,
+            &mut TextureAtlasSprite,
+            &mut BaseColor,
+        ),
+        (With<Npc>, Without<Santa>),
+    >,
+) {
+    let santa_position = santa_query.iter().next().map(|position| position.0);
+
+    for (mut transform, position, speed, state, mut animation_timer, mut sprite, mut base_color) in
+        query.iter_mut()
+    {
+        let direction = match (state, santa_position) {
+            // Alert residents stare at Santa.
+            (NpcState::Alert, Some(santa_position)) => santa_position.x - position.0.x,
+            _ => speed.0.x,
+        };
+        if direction > 0.0 {
+            transform.scale.x = 1.0;
+        } else if direction < 0.0 {
+            transform.scale.x = -1.0;
+        }
+
+        base_color.0 = match state {
+            NpcState::Sleeping => Color::rgb(0.4, 0.45, 0.7),
+            NpcState::Awake => Color::rgb(0.6, 0.7, 1.0),
+            NpcState::Alert => Color::rgb(1.0, 0.5, 0.5),
+        };
+
+        animation_timer.0.tick(time.delta());
+        if animation_timer.0.just_finished() {
+            if speed.0.x.abs() >= 1.0 {
+                sprite.index = 1 - sprite.index;
+            } else {
+                sprite.index = 0;
+            }
+        }
+    }
+}
+
+pub struct NpcPlugin;
+
+impl Plugin for NpcPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<NpcEvent>()
+            .add_system(
+                npc_senses_system
+                    .system()
+                    .label("npc_senses")
+                    .before("gravity"),
+            )
+            .add_system(
+                npc_patrol_system
+                    .system()
+                    .label("npc_patrol")
+                    .after("npc_senses")
+                    .before("gravity"),
+            )
+            .add_system(
+                animate_npc_system
+                    .system()
+                    .label("animate_npc")
+                    .after("npc_patrol")
+                    .before("gravity"),
+            );
+    }
+}
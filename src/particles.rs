@@ -0,0 +1,430 @@
+use crate::assets::{load_data, snowflake_atlas, SantaAssets};
+use crate::levels::{EmitterPlacement, LevelData, LevelState};
+use crate::physics::{Landed, Position, SpriteBoundary};
+use crate::player::Santa;
+use crate::rng::GameRng;
+use crate::TIME_STEP;
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, VertexAttributeValues};
+use bevy::render::pipeline::PrimitiveTopology;
+use bevy::sprite::SpriteResizeMode;
+use rand::distributions::{Distribution, Uniform};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde_derive::Deserialize;
+use std::collections::HashMap;
+
+const RNG_STREAM: &str = "particles";
+const PUFF_MIN_SPEED: f32 = 80.0;
+const PUFF_DEPTH: f32 = 1.1;
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct ParticleEffect {
+    // Particles per second, on top of the burst emitted when the emitter starts.
+    pub rate: f32,
+    pub burst: usize,
+    pub lifetime: (f32, f32),
+    pub velocity_min: Vec2,
+    pub velocity_max: Vec2,
+    // Half extents of the box particles are spawned in.
+    pub spread: Vec2,
+    pub gravity: f32,
+    pub frames: Vec<usize>,
+    pub color: [f32; 4],
+    pub start_scale: f32,
+    pub end_scale: f32,
+}
+
+impl Default for ParticleEffect {
+    fn default() -> Self {
+        Self {
+            rate: 0.0,
+            burst: 0,
+            lifetime: (1.0, 1.0),
+            velocity_min: Vec2::ZERO,
+            velocity_max: Vec2::ZERO,
+            spread: Vec2::ZERO,
+            gravity: 0.0,
+            frames: vec![3],
+            color: [1.0, 1.0, 1.0, 1.0],
+            start_scale: 1.0,
+            end_scale: 0.0,
+        }
+    }
+}
+
+impl ParticleEffect {
+    // Uniform panics on an inverted range and atlas lookups panic on a missing frame.
+    fn validate(&self, frame_count: usize) -> Result<(), String> {
+        if self.velocity_min.x > self.velocity_max.x || self.velocity_min.y > self.velocity_max.y {
+            return Err("velocity_min is above velocity_max".to_owned());
+        }
+        if self.lifetime.0 > self.lifetime.1 {
+            return Err("lifetime minimum is above its maximum".to_owned());
+        }
+        if self.spread.x < 0.0 || self.spread.y < 0.0 {
+            return Err("spread is negative".to_owned());
+        }
+        if let Some(frame) = self.frames.iter().find(|&&frame| frame >= frame_count) {
+            return Err(format!(
+                "frame {} is outside the {} snowflake sprites",
+                frame, frame_count
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default, Deserialize)]
+pub struct ParticleData {
+    pub effects: HashMap<String, ParticleEffect>,
+}
+
+impl ParticleData {
+    fn load() -> Self {
+        let mut particle_data = load_data::<ParticleData>("particles.ron").unwrap_or_default();
+        let frame_count = snowflake_atlas(Handle::default()).len();
+        particle_data
+            .effects
+            .retain(|name, effect| match effect.validate(frame_count) {
+                Ok(()) => true,
+                Err(error) => {
+                    error!("Invalid particle effect {}: {}", name, error);
+                    false
+                }
+            });
+        particle_data
+    }
+}
+
+struct Particle {
+    position: Vec2,
+    velocity: Vec2,
+    age: f32,
+    lifetime: f32,
+    index: usize,
+}
+
+impl Particle {
+    fn scale(&self, effect: &ParticleEffect) -> f32 {
+        let t = self.age / self.lifetime;
+        effect.start_scale + (effect.end_scale - effect.start_scale) * t
+    }
+}
+
+pub struct ParticleEmitter {
+    pub effect: ParticleEffect,
+    particles: Vec<Particle>,
+    accumulator: f32,
+    started: bool,
+    // One-shot emitters despawn once their burst has faded.
+    one_shot: bool,
+}
+
+impl ParticleEmitter {
+    pub fn continuous(effect: ParticleEffect) -> Self {
+        Self {
+            effect,
+            particles: Vec::new(),
+            accumulator: 0.0,
+            started: false,
+            one_shot: false,
+        }
+    }
+
+    pub fn one_shot(effect: ParticleEffect) -> Self {
+        Self {
+            one_shot: true,
+            ..Self::continuous(effect)
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.one_shot && self.started && self.particles.is_empty()
+    }
+
+    fn emit<R: Rng>(&mut self, rng: &mut R, amount: usize) {
+        let effect = &self.effect;
+        for _ in 0..amount {
+            let spread = Vec2::new(
+                Uniform::new_inclusive(-effect.spread.x, effect.spread.x).sample(rng),
+                Uniform::new_inclusive(-effect.spread.y, effect.spread.y).sample(rng),
+            );
+            let velocity = Vec2::new(
+                Uniform::new_inclusive(effect.velocity_min.x, effect.velocity_max.x).sample(rng),
+                Uniform::new_inclusive(effect.velocity_min.y, effect.velocity_max.y).sample(rng),
+            );
+            self.particles.push(Particle {
+                position: spread,
+                velocity,
+                age: 0.0,
+                lifetime: Uniform::new_inclusive(effect.lifetime.0, effect.lifetime.1)
+                    .sample(rng)
+                    .max(f32::EPSILON),
+                index: effect.frames.choose(rng).copied().unwrap_or(0),
+            });
+        }
+    }
+
+    pub fn update<R: Rng>(&mut self, rng: &mut R, seconds: f32) {
+        if !self.started {
+            self.started = true;
+            let burst = self.effect.burst;
+            self.emit(rng, burst);
+        }
+        if !self.one_shot {
+            self.accumulator += self.effect.rate * seconds;
+            let amount = self.accumulator.floor();
+            self.accumulator -= amount;
+            self.emit(rng, amount as usize);
+        }
+
+        let gravity = self.effect.gravity;
+        for particle in self.particles.iter_mut() {
+            particle.age += seconds;
+            particle.velocity.y -= gravity * seconds;
+            particle.position += particle.velocity * seconds;
+        }
+        self.particles
+            .retain(|particle| particle.age < particle.lifetime);
+    }
+}
+
+// Quads of atlas sprites at (position, scale, atlas index), drawn as a single mesh through
+// the sprite pipeline with a unit size.
+pub fn build_sprite_batch<I: Iterator<Item = (Vec2, f32, usize)>>(
+    sprites: I,
+    atlas: &TextureAtlas,
+) -> Mesh {
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    fill_sprite_batch(&mut mesh, sprites, atlas);
+    mesh
+}
+
+fn take_float3(mesh: &mut Mesh, name: &'static str) -> Vec<[f32; 3]> {
+    match mesh.attribute_mut(name) {
+        Some(VertexAttributeValues::Float3(values)) => std::mem::take(values),
+        _ => Vec::new(),
+    }
+}
+
+// Refills the mesh of a batch every frame, reusing the buffers it already has.
+pub fn fill_sprite_batch<I: Iterator<Item = (Vec2, f32, usize)>>(
+    mesh: &mut Mesh,
+    sprites: I,
+    atlas: &TextureAtlas,
+) {
+    let mut positions = take_float3(mesh, Mesh::ATTRIBUTE_POSITION);
+    let mut normals = take_float3(mesh, Mesh::ATTRIBUTE_NORMAL);
+    let mut uvs = match mesh.attribute_mut(Mesh::ATTRIBUTE_UV_0) {
+        Some(VertexAttributeValues::Float2(values)) => std::mem::take(values),
+        _ => Vec::new(),
+    };
+    let mut indices = match mesh.indices_mut() {
+        Some(Indices::U32(values)) => std::mem::take(values),
+        _ => Vec::new(),
+    };
+    positions.clear();
+    normals.clear();
+    uvs.clear();
+    indices.clear();
+
+    for (position, scale, index) in sprites {
+        let rect = atlas.textures[index];
+        let half_size = (rect.max - rect.min) / 2.0 * scale;
+        let uv_min = rect.min / atlas.size;
+        let uv_max = rect.max / atlas.size;
+        let min = position - half_size;
+        let max = position + half_size;
+
+        let base = positions.len() as u32;
+        positions.extend_from_slice(&[
+            [min.x, min.y, 0.0],
+            [max.x, min.y, 0.0],
+            [max.x, max.y, 0.0],
+            [min.x, max.y, 0.0],
+        ]);
+        normals.extend_from_slice(&[[0.0, 0.0, 1.0]; 4]);
+        uvs.extend_from_slice(&[
+            [uv_min.x, uv_max.y],
+            [uv_max.x, uv_max.y],
+            [uv_max.x, uv_min.y],
+            [uv_min.x, uv_min.y],
+        ]);
+        indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+    }
+
+    mesh.set_attribute(
+        Mesh::ATTRIBUTE_POSITION,
+        VertexAttributeValues::Float3(positions),
+    );
+    mesh.set_attribute(
+        Mesh::ATTRIBUTE_NORMAL,
+        VertexAttributeValues::Float3(normals),
+    );
+    mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, VertexAttributeValues::Float2(uvs));
+    mesh.set_indices(Some(Indices::U32(indices)));
+}
+
+fn emitter_bundle(
+    effect: &ParticleEffect,
+    translation: Vec3,
+    atlas: &TextureAtlas,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<ColorMaterial>,
+) -> SpriteBundle {
+    let [red, green, blue, alpha] = effect.color;
+    SpriteBundle {
+        sprite: Sprite {
+            size: Vec2::ONE,
+            resize_mode: SpriteResizeMode::Manual,
+            ..Default::default()
+        },
+        mesh: meshes.add(build_sprite_batch(std::iter::empty(), atlas)),
+        material: materials.add(ColorMaterial {
+            color: Color::rgba(red, green, blue, alpha),
+            texture: Some(atlas.texture.clone()),
+        }),
+        // An empty mesh has no vertex buffer to bind, so stay hidden until the first particle.
+        visible: Visible {
+            is_visible: false,
+            is_transparent: true,
+        },
+        transform: Transform::from_translation(translation),
+        ..Default::default()
+    }
+}
+
+pub fn spawn_emitters(
+    parent: &mut ChildBuilder,
+    placements: &[EmitterPlacement],
+    particle_data: &ParticleData,
+    santa_assets: &SantaAssets,
+    texture_atlases: &Assets<TextureAtlas>,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<ColorMaterial>,
+) {
+    let atlas = texture_atlases.get(&santa_assets.snowflakes).unwrap();
+    for placement in placements {
+        let effect = match particle_data.effects.get(&placement.effect) {
+            Some(effect) => effect,
+            None => {
+                error!("Unknown particle effect {}", placement.effect);
+                continue;
+            }
+        };
+        parent
+            .spawn_bundle(emitter_bundle(
+                effect,
+                placement.position.extend(placement.depth),
+                atlas,
+                meshes,
+                materials,
+            ))
+            .insert(ParticleEmitter::continuous(effect.clone()));
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn landing_puff_system(
+    mut commands: Commands,
+    state: Res<State<LevelState>>,
+    level_data: Res<LevelData>,
+    particle_data: Res<ParticleData>,
+    santa_assets: Res<SantaAssets>,
+    texture_atlases: Res<Assets<TextureAtlas>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    santa_query: Query<(&Position, &SpriteBoundary), With<Santa>>,
+    mut landed_events: EventReader<Landed>,
+) {
+    let effect = level_data
+        .get(state.current())
+        .landing_effect
+        .as_ref()
+        .and_then(|effect| particle_data.effects.get(effect));
+    let atlas = texture_atlases.get(&santa_assets.snowflakes);
+
+    for landed in landed_events
+        .iter()
+        .filter(|landed| landed.impact_speed >= PUFF_MIN_SPEED)
+    {
+        let (effect, atlas) = match (effect, atlas) {
+            (Some(effect), Some(atlas)) => (effect, atlas),
+            _ => continue,
+        };
+        let (position, sprite_boundary) = match santa_query.get(landed.entity) {
+            Ok(santa) => santa,
+            Err(_) => continue,
+        };
+        let feet = position.0 + Vec2::new(0.0, sprite_boundary.0.bottom);
+
+        commands
+            .spawn_bundle(emitter_bundle(
+                effect,
+                feet.extend(PUFF_DEPTH),
+                atlas,
+                &mut meshes,
+                &mut materials,
+            ))
+            .insert(ParticleEmitter::one_shot(effect.clone()));
+    }
+}
+
+fn update_particles_system(
+    mut commands: Commands,
+    santa_assets: Res<SantaAssets>,
+    texture_atlases: Res<Assets<TextureAtlas>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut game_rng: ResMut<GameRng>,
+    mut emitter_query: Query<(Entity, &mut ParticleEmitter, &Handle<Mesh>, &mut Visible)>,
+) {
+    let atlas = match texture_atlases.get(&santa_assets.snowflakes) {
+        Some(atlas) => atlas,
+        None => return,
+    };
+    let rng = game_rng.stream(RNG_STREAM);
+
+    for (entity, mut emitter, mesh, mut visible) in emitter_query.iter_mut() {
+        emitter.update(rng, TIME_STEP);
+        if emitter.is_finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        visible.is_visible = !emitter.particles.is_empty();
+        if visible.is_visible {
+            if let Some(mesh) = meshes.get_mut(mesh) {
+                let effect = &emitter.effect;
+                fill_sprite_batch(
+                    mesh,
+                    emitter.particles.iter().map(|particle| {
+                        (particle.position, particle.scale(effect), particle.index)
+                    }),
+                    atlas,
+                );
+            }
+        }
+    }
+}
+
+pub struct ParticlesPlugin;
+
+impl Plugin for ParticlesPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(ParticleData::load())
+            .add_system(
+                landing_puff_system
+                    .system()
+                    .label("landing_puff")
+                    .after("level_boundary"),
+            )
+            .add_system(
+                update_particles_system
+                    .system()
+                    .label("update_particles")
+                    .after("landing_puff"),
+            );
+    }
+}
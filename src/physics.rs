@@ -1,3 +1,4 @@
+use crate::console::{parse_arg, ConsoleAppExt};
 use crate::levels::LevelPlayerBoundary;
 use crate::TIME_STEP;
 use bevy::prelude::*;
@@ -17,30 +18,50 @@ pub struct GroundState {
 pub const GRAVITY: f32 = 450.0;
 pub struct Gravity;
 
+// Gravity currently applied, which can differ from `GRAVITY` while testing.
+pub struct GravityStrength(pub f32);
+
+impl Default for GravityStrength {
+    fn default() -> Self {
+        Self(GRAVITY)
+    }
+}
+
 pub struct SpriteBoundary(pub Rect<f32>);
 
+pub struct Landed {
+    pub entity: Entity,
+    pub impact_speed: f32,
+}
+
 fn move_system(mut query: Query<(&mut Position, &Speed)>) {
     for (mut position, speed) in query.iter_mut() {
         position.0 += speed.0 * TIME_STEP;
     }
 }
 
-fn gravity_system(mut query: Query<&mut Speed, With<Gravity>>) {
+fn gravity_system(
+    gravity_strength: Res<GravityStrength>,
+    mut query: Query<&mut Speed, With<Gravity>>,
+) {
     for mut speed in query.iter_mut() {
-        speed.0.y -= GRAVITY * TIME_STEP;
+        speed.0.y -= gravity_strength.0 * TIME_STEP;
     }
 }
 
 fn level_boundary_system(
     level_boundary: Res<LevelPlayerBoundary>,
+    mut landed_events: EventWriter<Landed>,
     mut query: Query<(
+        Entity,
         &mut Position,
         &mut Speed,
         &SpriteBoundary,
         Option<&mut GroundState>,
     )>,
 ) {
-    for (mut position, mut speed, sprite_boundary, ground_state) in query.iter_mut() {
+    for (entity, mut position, mut speed, sprite_boundary, ground_state) in query.iter_mut() {
+        let vertical_speed = speed.0.y;
         let min_x = level_boundary.0.left - sprite_boundary.0.left;
         let max_x = level_boundary.0.right - sprite_boundary.0.right;
         let min_y = level_boundary.0.bottom - sprite_boundary.0.bottom;
@@ -69,15 +90,37 @@ fn level_boundary_system(
         if let Some(mut ground_state) = ground_state {
             ground_state.just_landed = !ground_state.on_ground && detected_on_ground;
             ground_state.on_ground = detected_on_ground;
+
+            if ground_state.just_landed {
+                landed_events.send(Landed {
+                    entity,
+                    impact_speed: -vertical_speed,
+                });
+            }
         }
     }
 }
 
+fn gravity_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let mut gravity_strength = world.get_resource_mut::<GravityStrength>().unwrap();
+    if !args.is_empty() {
+        gravity_strength.0 = parse_arg(args, 0, "gravity")?;
+    }
+    Ok(format!("Gravity is {}", gravity_strength.0))
+}
+
 pub struct SantaPhysicsPlugin;
 
 impl Plugin for SantaPhysicsPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_system(gravity_system.system().label("gravity").before("move"))
+        app.add_event::<Landed>()
+            .init_resource::<GravityStrength>()
+            .add_console_command(
+                "gravity",
+                "gravity [VALUE] - show or set the gravity",
+                gravity_command,
+            )
+            .add_system(gravity_system.system().label("gravity").before("move"))
             .add_system(move_system.system().label("move"))
             .add_system(
                 level_boundary_system
@@ -1,4 +1,7 @@
 use crate::assets::SantaAssets;
+use crate::console::{parse_arg, ConsoleAppExt};
+use crate::controls::{Action, ActionState};
+use crate::dialogue_graph::ChoiceMenu;
 use crate::physics::{Gravity, GroundState, Position, Speed, SpriteBoundary, GRAVITY};
 use crate::TIME_STEP;
 use bevy::prelude::*;
@@ -16,6 +19,10 @@ pub struct Santa;
 
 pub struct AnimationTimer(pub Timer);
 
+pub struct Jumped(pub Entity);
+
+pub struct Footstep(pub Entity);
+
 fn init_santa_system(mut commands: Commands, assets: Res<SantaAssets>) {
     commands
         .spawn()
@@ -39,17 +46,27 @@ fn init_santa_system(mut commands: Commands, assets: Res<SantaAssets>) {
 }
 
 fn control_santa_system(
-    keyboard_input: Res<Input<KeyCode>>,
-    mut santa_query: Query<(&mut Speed, &GroundState), With<Santa>>,
+    action_state: Res<ActionState>,
+    mut santa_query: Query<(Entity, &mut Speed, &GroundState), With<Santa>>,
+    choice_menu_query: Query<(), With<ChoiceMenu>>,
+    mut jumped_events: EventWriter<Jumped>,
+    mut jump_blocked: Local<bool>,
 ) {
-    for (mut speed, ground_state) in santa_query.iter_mut() {
+    // Santa stands still while the player picks a dialogue choice.
+    let choosing = choice_menu_query.iter().next().is_some();
+    // Jump shares keys with the menu, so a key still held from confirming a choice must be
+    // released before it jumps.
+    if choosing {
+        *jump_blocked = true;
+    } else if !action_state.pressed(Action::Jump) {
+        *jump_blocked = false;
+    }
+
+    for (entity, mut speed, ground_state) in santa_query.iter_mut() {
         if ground_state.on_ground {
-            let left = keyboard_input.pressed(KeyCode::A) || keyboard_input.pressed(KeyCode::Left);
-            let right =
-                keyboard_input.pressed(KeyCode::D) || keyboard_input.pressed(KeyCode::Right);
-            let jump = keyboard_input.pressed(KeyCode::W)
-                || keyboard_input.pressed(KeyCode::Up)
-                || keyboard_input.pressed(KeyCode::Space);
+            let left = !choosing && action_state.pressed(Action::Left);
+            let right = !choosing && action_state.pressed(Action::Right);
+            let jump = !*jump_blocked && action_state.pressed(Action::Jump);
 
             let mut accelerating = false;
             if left && !right {
@@ -82,6 +99,7 @@ fn control_santa_system(
 
             if jump {
                 speed.0.y = *JUMP_POWER;
+                jumped_events.send(Jumped(entity));
             }
         }
     }
@@ -89,8 +107,10 @@ fn control_santa_system(
 
 fn animate_santa_system(
     time: Res<Time>,
+    mut footstep_events: EventWriter<Footstep>,
     mut query: Query<
         (
+            Entity,
             &mut Transform,
             &Speed,
             &GroundState,
@@ -100,7 +120,9 @@ fn animate_santa_system(
         With<Santa>,
     >,
 ) {
-    for (mut transform, speed, ground_state, mut animation_timer, mut sprite) in query.iter_mut() {
+    for (entity, mut transform, speed, ground_state, mut animation_timer, mut sprite) in
+        query.iter_mut()
+    {
         let mut moving = false;
 
         if speed.0.x > 0.0 {
@@ -115,6 +137,9 @@ fn animate_santa_system(
         if animation_timer.0.just_finished() {
             if moving {
                 sprite.index = 1 - sprite.index;
+                if ground_state.on_ground {
+                    footstep_events.send(Footstep(entity));
+                }
             } else {
                 sprite.index = 0;
             }
@@ -133,11 +158,24 @@ fn animate_santa_system(
     }
 }
 
+fn teleport_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let target = Vec2::new(parse_arg(args, 0, "x")?, parse_arg(args, 1, "y")?);
+    let mut query = world.query_filtered::<(&mut Position, &mut Speed), With<Santa>>();
+    for (mut position, mut speed) in query.iter_mut(world) {
+        position.0 = target;
+        speed.0 = Vec2::ZERO;
+    }
+    Ok(format!("Teleported to ({}, {})", target.x, target.y))
+}
+
 pub struct SantaPlayerPlugin;
 
 impl Plugin for SantaPlayerPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_startup_system(init_santa_system.system().label("init_santa"))
+        app.add_event::<Jumped>()
+            .add_event::<Footstep>()
+            .add_startup_system(init_santa_system.system().label("init_santa"))
+            .add_console_command("tp", "tp X Y - move Santa", teleport_command)
             .add_system(control_santa_system.system().label("control_santa"))
             .add_system(
                 animate_santa_system
@@ -1,5 +1,9 @@
+use crate::camera::SantaOrthoProjection;
 use crate::physics::Position;
 use bevy::prelude::*;
+use bevy::render::camera::Camera;
+
+pub struct ParallaxLayer(pub f32);
 
 fn position_sprites_system(mut query: Query<(&mut Transform, &Position)>) {
     for (mut transform, position) in query.iter_mut() {
@@ -8,10 +12,48 @@ fn position_sprites_system(mut query: Query<(&mut Transform, &Position)>) {
     }
 }
 
+fn parallax_system(
+    camera_query: Query<(&Transform, &SantaOrthoProjection), With<Camera>>,
+    mut layer_query: Query<(&ParallaxLayer, &Sprite, &mut Transform), Without<Camera>>,
+) {
+    let (camera_transform, projection) = match camera_query.iter().next() {
+        Some(camera) => camera,
+        None => return,
+    };
+    let camera_position = camera_transform.translation.truncate();
+    let canvas = &projection.canvas;
+
+    for (parallax_layer, sprite, mut transform) in layer_query.iter_mut() {
+        let mut position = camera_position * (1.0 - parallax_layer.0);
+
+        // Keep the layer covering the visible canvas so its edges never show.
+        let min = camera_position + Vec2::new(canvas.right, canvas.top) - sprite.size / 2.0;
+        let max = camera_position + Vec2::new(canvas.left, canvas.bottom) + sprite.size / 2.0;
+        if min.x <= max.x {
+            position.x = position.x.max(min.x).min(max.x);
+        }
+        if min.y <= max.y {
+            position.y = position.y.max(min.y).min(max.y);
+        }
+
+        if projection.pixel_perfect {
+            position = position.round();
+        }
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+    }
+}
+
 pub struct SantaRenderPlugin;
 
 impl Plugin for SantaRenderPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_system(position_sprites_system.system().label("position_sprites"));
+        app.add_system(position_sprites_system.system().label("position_sprites"))
+            .add_system(
+                parallax_system
+                    .system()
+                    .label("parallax")
+                    .after("follow_player_camera"),
+            );
     }
 }
@@ -0,0 +1,229 @@
+use crate::cli::CliArgs;
+use crate::console::Console;
+use crate::controls::ActionState;
+use crate::levels::LevelState;
+use crate::rng::GameRng;
+use bevy::app::AppExit;
+use bevy::prelude::*;
+use ron::ser::PrettyConfig;
+use serde_derive::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+// Rewrite the recording every so often so a crash still leaves most of it behind.
+const FLUSH_FRAMES: usize = 600;
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Recording {
+    pub seed: u64,
+    // Start arguments the game was launched with, applied again on replay.
+    #[serde(default)]
+    pub level: Option<String>,
+    #[serde(default)]
+    pub spawn: Option<(f32, f32)>,
+    #[serde(default)]
+    pub skip_intro: bool,
+    // Action bits of every frame, see `ActionState`.
+    pub frames: Vec<u32>,
+    // Console lines with the frame they ran in.
+    #[serde(default)]
+    pub commands: Vec<(usize, String)>,
+}
+
+impl Recording {
+    pub fn new(cli_args: &CliArgs) -> Self {
+        Self {
+            level: cli_args.level.as_ref().map(|level| level.key().to_owned()),
+            spawn: cli_args.spawn.map(|spawn| (spawn.x, spawn.y)),
+            skip_intro: cli_args.skip_intro,
+            ..Default::default()
+        }
+    }
+
+    pub fn apply_start_args(&self, cli_args: &mut CliArgs) {
+        cli_args.level = self.level.as_deref().and_then(LevelState::from_key);
+        cli_args.spawn = self.spawn.map(|(x, y)| Vec2::new(x, y));
+        cli_args.skip_intro = self.skip_intro;
+    }
+
+    pub fn load(path: &Path) -> Option<Recording> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(error) => {
+                error!("Could not read recording {:?}: {}", path, error);
+                return None;
+            }
+        };
+
+        match ron::de::from_str(&content) {
+            Ok(recording) => Some(recording),
+            Err(error) => {
+                error!("Could not parse recording {:?}: {}", path, error);
+                None
+            }
+        }
+    }
+
+    pub fn save(&self, path: &Path) {
+        let content = match ron::ser::to_string_pretty(self, PrettyConfig::new()) {
+            Ok(content) => content,
+            Err(error) => {
+                error!("Could not serialize recording: {}", error);
+                return;
+            }
+        };
+
+        if let Err(error) = std::fs::write(path, content) {
+            error!("Could not save recording to {:?}: {}", path, error);
+        }
+    }
+}
+
+pub struct InputRecorder {
+    pub path: Option<PathBuf>,
+    pub recording: Recording,
+}
+
+impl InputRecorder {
+    pub fn new(path: Option<PathBuf>, cli_args: &CliArgs) -> Self {
+        Self {
+            path,
+            recording: Recording::new(cli_args),
+        }
+    }
+
+    // Console commands run at the end of `PreUpdate`, after this frame's actions were recorded.
+    pub fn record_command(&mut self, line: &str) {
+        let frame = self.recording.frames.len().saturating_sub(1);
+        self.recording.commands.push((frame, line.to_owned()));
+    }
+
+    fn save(&self) {
+        if let Some(path) = &self.path {
+            self.recording.save(path);
+        }
+    }
+}
+
+pub struct InputPlayback {
+    pub recording: Recording,
+    pub frame: usize,
+}
+
+impl InputPlayback {
+    pub fn new(recording: Recording) -> Self {
+        Self {
+            recording,
+            frame: 0,
+        }
+    }
+}
+
+fn playback_system(
+    mut commands: Commands,
+    playback: Option<ResMut<InputPlayback>>,
+    console: Option<ResMut<Console>>,
+    mut action_state: ResMut<ActionState>,
+) {
+    let mut playback = match playback {
+        Some(playback) => playback,
+        None => return,
+    };
+
+    match playback.recording.frames.get(playback.frame) {
+        Some(bits) => {
+            action_state.replace(*bits);
+            if let Some(mut console) = console {
+                let frame = playback.frame;
+                for (_, line) in playback
+                    .recording
+                    .commands
+                    .iter()
+                    .filter(|(command_frame, _)| *command_frame == frame)
+                {
+                    console.submit(line.clone());
+                }
+            }
+            playback.frame += 1;
+        }
+        None => {
+            info!(
+                "Replay finished after {} frames, handing control back",
+                playback.frame
+            );
+            commands.remove_resource::<InputPlayback>();
+        }
+    }
+}
+
+fn record_system(
+    game_rng: Res<GameRng>,
+    recorder: Option<ResMut<InputRecorder>>,
+    action_state: Res<ActionState>,
+) {
+    let mut recorder = match recorder {
+        Some(recorder) => recorder,
+        None => return,
+    };
+
+    recorder.recording.seed = game_rng.seed();
+    recorder.recording.frames.push(action_state.bits());
+    if recorder.recording.frames.len() % FLUSH_FRAMES == 0 {
+        recorder.save();
+    }
+}
+
+fn save_recording_on_exit_system(
+    recorder: Option<Res<InputRecorder>>,
+    mut app_exit_events: EventReader<AppExit>,
+) {
+    if let Some(recorder) = recorder {
+        if app_exit_events.iter().next().is_some() {
+            recorder.save();
+        }
+    }
+}
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        let mut cli_args = app
+            .world()
+            .get_resource::<CliArgs>()
+            .cloned()
+            .unwrap_or_default();
+
+        if let Some(path) = cli_args.replay.clone() {
+            if let Some(recording) = Recording::load(&path) {
+                info!(
+                    "Replaying {} frames from {:?} with seed {}",
+                    recording.frames.len(),
+                    path,
+                    recording.seed
+                );
+                // The level and dialogue plugins read the start arguments when they build.
+                recording.apply_start_args(&mut cli_args);
+                app.insert_resource(cli_args.clone())
+                    .insert_resource(GameRng::new(recording.seed))
+                    .insert_resource(InputPlayback::new(recording));
+            }
+        }
+        if let Some(path) = cli_args.record.clone() {
+            info!("Recording input to {:?}", path);
+            app.insert_resource(InputRecorder::new(Some(path), &cli_args));
+        }
+
+        app.add_system_to_stage(
+            CoreStage::PreUpdate,
+            playback_system
+                .system()
+                .label("playback")
+                .after("update_actions"),
+        )
+        .add_system_to_stage(
+            CoreStage::PreUpdate,
+            record_system.system().label("record").after("playback"),
+        )
+        .add_system_to_stage(CoreStage::Last, save_recording_on_exit_system.system());
+    }
+}
@@ -0,0 +1,64 @@
+use crate::cli::CliArgs;
+use crate::config::UserConfig;
+use bevy::prelude::*;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::collections::HashMap;
+
+// Every consumer draws from its own stream, so the order in which systems run does not change
+// the numbers any of them get.
+pub struct GameRng {
+    seed: u64,
+    streams: HashMap<&'static str, StdRng>,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            streams: HashMap::new(),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn stream(&mut self, name: &'static str) -> &mut StdRng {
+        let seed = self.seed;
+        self.streams
+            .entry(name)
+            .or_insert_with(|| StdRng::seed_from_u64(seed ^ stream_hash(name)))
+    }
+}
+
+// FNV-1a, spelled out because the std hashers are not guaranteed to be stable.
+fn stream_hash(name: &str) -> u64 {
+    name.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+pub struct SantaRngPlugin;
+
+impl Plugin for SantaRngPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        // A replay or a test harness may already have seeded the game.
+        if app.world().contains_resource::<GameRng>() {
+            return;
+        }
+
+        let cli_seed = app
+            .world()
+            .get_resource::<CliArgs>()
+            .and_then(|cli_args| cli_args.seed);
+        let config_seed = app
+            .world()
+            .get_resource::<UserConfig>()
+            .and_then(|config| config.seed);
+        let seed = cli_seed.or(config_seed).unwrap_or_else(rand::random);
+        info!("Using random seed {}", seed);
+
+        app.insert_resource(GameRng::new(seed));
+    }
+}
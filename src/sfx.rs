@@ -0,0 +1,119 @@
+use crate::assets::SantaAssets;
+use crate::audio::SantaAudio;
+use crate::levels::{LevelData, LevelState};
+use crate::physics::Landed;
+use crate::player::{Footstep, Jumped, Santa};
+use crate::rng::GameRng;
+use bevy::prelude::*;
+use rand::distributions::{Distribution, Uniform};
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+const VOLUME_VARIATION: f32 = 0.15;
+const PITCH_VARIATION: f32 = 0.1;
+const FOOTSTEP_VOLUME: f32 = 0.5;
+const JUMP_VOLUME: f32 = 0.7;
+const MIN_LANDING_VOLUME: f32 = 0.3;
+const HARD_LANDING_SPEED: f32 = 150.0;
+const RNG_STREAM: &str = "sfx";
+
+fn play_variation<R: Rng>(
+    rng: &mut R,
+    santa_audio: &mut SantaAudio,
+    santa_assets: &SantaAssets,
+    audio_sources: &Assets<AudioSource>,
+    sounds: &[String],
+    volume: f32,
+) {
+    let audio_source = sounds
+        .choose(rng)
+        .and_then(|path| santa_assets.sfx.get(path))
+        .and_then(|handle| audio_sources.get(handle));
+
+    if let Some(audio_source) = audio_source {
+        let volume = volume
+            * Uniform::new_inclusive(1.0 - VOLUME_VARIATION, 1.0 + VOLUME_VARIATION).sample(rng);
+        let speed =
+            Uniform::new_inclusive(1.0 - PITCH_VARIATION, 1.0 + PITCH_VARIATION).sample(rng);
+        santa_audio.play_sfx(audio_source, volume, speed);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn surface_sfx_system(
+    state: Res<State<LevelState>>,
+    level_data: Res<LevelData>,
+    santa_assets: Res<SantaAssets>,
+    audio_sources: Res<Assets<AudioSource>>,
+    mut santa_audio: NonSendMut<SantaAudio>,
+    mut game_rng: ResMut<GameRng>,
+    santa_query: Query<(), With<Santa>>,
+    mut landed_events: EventReader<Landed>,
+    mut jumped_events: EventReader<Jumped>,
+    mut footstep_events: EventReader<Footstep>,
+) {
+    let surface = match level_data.surface(state.current()) {
+        Some(surface) => surface,
+        None => return,
+    };
+    let rng = game_rng.stream(RNG_STREAM);
+
+    for _ in footstep_events
+        .iter()
+        .filter(|footstep| santa_query.get(footstep.0).is_ok())
+    {
+        play_variation(
+            rng,
+            &mut santa_audio,
+            &santa_assets,
+            &audio_sources,
+            &surface.footsteps,
+            FOOTSTEP_VOLUME,
+        );
+    }
+
+    for _ in jumped_events
+        .iter()
+        .filter(|jumped| santa_query.get(jumped.0).is_ok())
+    {
+        play_variation(
+            rng,
+            &mut santa_audio,
+            &santa_assets,
+            &audio_sources,
+            &surface.jump,
+            JUMP_VOLUME,
+        );
+    }
+
+    for landed in landed_events
+        .iter()
+        .filter(|landed| santa_query.get(landed.entity).is_ok())
+    {
+        let volume = (landed.impact_speed / HARD_LANDING_SPEED)
+            .max(MIN_LANDING_VOLUME)
+            .min(1.0);
+        play_variation(
+            rng,
+            &mut santa_audio,
+            &santa_assets,
+            &audio_sources,
+            &surface.land,
+            volume,
+        );
+    }
+}
+
+pub struct SantaSfxPlugin;
+
+impl Plugin for SantaSfxPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(
+            surface_sfx_system
+                .system()
+                .label("surface_sfx")
+                .after("level_boundary")
+                .after("animate_santa"),
+        );
+    }
+}
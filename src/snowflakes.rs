@@ -1,106 +1,539 @@
 use crate::assets::SantaAssets;
-use crate::levels::LevelCameraBoundary;
-use crate::physics::Position;
+use crate::levels::{LevelCameraBoundary, SnowSurface};
+use crate::particles::{build_sprite_batch, fill_sprite_batch};
+use crate::physics::{Position, SpriteBoundary};
+use crate::player::{Footstep, Santa};
+use crate::rng::GameRng;
+use crate::weather::Weather;
 use crate::TIME_STEP;
 use bevy::prelude::*;
+use bevy::render::texture::{Extent3d, TextureDimension, TextureFormat};
+use bevy::sprite::SpriteResizeMode;
 use noise::{BasicMulti, MultiFractal, NoiseFn, Seedable};
 use rand::distributions::{Distribution, Uniform};
-use rand::thread_rng;
+use rand::Rng;
 
 static SNOWFLAKE_DENSITY: f32 = 0.002;
+const SNOWFLAKE_FALL_SPEED: f32 = 10.0;
+const MAX_INTENSITY: f32 = 2.0;
+const FIELD_WIDTH: usize = 160;
+const FIELD_HEIGHT: usize = 64;
+const RNG_STREAM: &str = "snowflakes";
+const FIELD_CELL: f32 = 4.0;
+const FIELD_SCROLL_X: f32 = 6.0;
+const FIELD_SCROLL_Y: f32 = 2.5;
+const DISPLACEMENT_X: f32 = 40.0;
+const DISPLACEMENT_Y: f32 = 25.0;
+const SWAY_FREQUENCY: f32 = 1.5;
+const SWAY_AMPLITUDE: f32 = 6.0;
+const MAX_SNOW_DEPTH: f32 = 6.0;
+const INITIAL_SNOW_DEPTH: f32 = 2.0;
+const SNOW_PER_FLAKE: f32 = 0.3;
+const SNOW_SPREAD: usize = 2;
+const FOOTPRINT_HALF_WIDTH: usize = 3;
+const FOOTPRINT_DEPTH: f32 = 0.3;
+const FOOTPRINT_TOLERANCE: f32 = 2.0;
+
+// The layers split one density budget, so adding depth does not add flakes.
+pub const SNOWFLAKE_LAYERS: [SnowflakeLayer; 2] = [
+    SnowflakeLayer {
+        depth: 0.5,
+        share: 0.65,
+        scale: 0.7,
+        speed: 0.6,
+        drift: 0.6,
+        alpha: 0.55,
+        lands: true,
+    },
+    SnowflakeLayer {
+        depth: 1.6,
+        share: 0.35,
+        scale: 1.2,
+        speed: 1.5,
+        drift: 1.3,
+        alpha: 0.9,
+        lands: false,
+    },
+];
+
+#[derive(Clone, Copy, Debug)]
+pub struct SnowflakeLayer {
+    pub depth: f32,
+    pub share: f32,
+    pub scale: f32,
+    pub speed: f32,
+    pub drift: f32,
+    pub alpha: f32,
+    pub lands: bool,
+}
 
 #[derive(Default, Clone, Debug)]
-struct Snowflake(Vec2);
+pub struct Snowflake {
+    pub origin: Vec2,
+    pub position: Vec2,
+    // Only shown while the weather intensity is above this threshold.
+    pub threshold: f32,
+    pub phase: f32,
+    // Between 0 and 1; bigger flakes use a bigger sprite, fall faster and drift more.
+    pub size: f32,
+    pub index: usize,
+}
+
+impl Snowflake {
+    fn weight(&self) -> f32 {
+        0.5 + self.size
+    }
+}
+
+pub struct Snowflakes {
+    pub layer: SnowflakeLayer,
+    pub flakes: Vec<Snowflake>,
+}
+
+pub struct Snowfall {
+    pub area: Rect<f32>,
+    pub velocity: Vec2,
+    pub turbulence: f32,
+}
+
+// Flow field sampled once from the noise, so flakes only need a bilinear lookup. The grid is
+// wrapped around a torus in four dimensional noise, so its edges meet without a seam.
+pub struct SnowflakeNoise {
+    field: Vec<Vec2>,
+}
+
+impl SnowflakeNoise {
+    pub fn new(seed_x: u32, seed_y: u32) -> Self {
+        let noise = |seed| {
+            BasicMulti::new()
+                .set_seed(seed)
+                .set_octaves(6)
+                .set_frequency(0.02)
+                .set_lacunarity(1.5)
+                .set_persistence(0.7)
+        };
+        let noise_x = noise(seed_x);
+        let noise_y = noise(seed_y);
+
+        // Radii keeping neighbouring cells FIELD_CELL apart on the torus.
+        let radius_x = (FIELD_WIDTH as f32 * FIELD_CELL / std::f32::consts::TAU) as f64;
+        let radius_y = (FIELD_HEIGHT as f32 * FIELD_CELL / std::f32::consts::TAU) as f64;
+        let mut field = Vec::with_capacity(FIELD_WIDTH * FIELD_HEIGHT);
+        for row in 0..FIELD_HEIGHT {
+            let angle_y = row as f64 / FIELD_HEIGHT as f64 * std::f64::consts::TAU;
+            for column in 0..FIELD_WIDTH {
+                let angle_x = column as f64 / FIELD_WIDTH as f64 * std::f64::consts::TAU;
+                let point = [
+                    radius_x * angle_x.cos(),
+                    radius_x * angle_x.sin(),
+                    radius_y * angle_y.cos(),
+                    radius_y * angle_y.sin(),
+                ];
+                field.push(Vec2::new(
+                    noise_x.get(point) as f32,
+                    noise_y.get(point) as f32,
+                ));
+            }
+        }
+        Self { field }
+    }
+
+    fn cell(&self, column: i64, row: i64) -> Vec2 {
+        let column = column.rem_euclid(FIELD_WIDTH as i64) as usize;
+        let row = row.rem_euclid(FIELD_HEIGHT as i64) as usize;
+        self.field[row * FIELD_WIDTH + column]
+    }
+
+    pub fn sample(&self, position: Vec2) -> Vec2 {
+        let x = position.x / FIELD_CELL;
+        let y = position.y / FIELD_CELL;
+        let column = x.floor();
+        let row = y.floor();
+        let (fraction_x, fraction_y) = (x - column, y - row);
+        let (column, row) = (column as i64, row as i64);
+
+        let bottom = self
+            .cell(column, row)
+            .lerp(self.cell(column + 1, row), fraction_x);
+        let top = self
+            .cell(column, row + 1)
+            .lerp(self.cell(column + 1, row + 1), fraction_x);
+        bottom.lerp(top, fraction_y)
+    }
+}
+
+impl FromWorld for SnowflakeNoise {
+    fn from_world(world: &mut World) -> Self {
+        let mut game_rng = world.get_resource_mut::<GameRng>().unwrap();
+        let rng = game_rng.stream("snowflake_noise");
+        Self::new(rng.gen(), rng.gen())
+    }
+}
+
+pub struct SnowCover {
+    left: f32,
+    bottom: f32,
+    heights: Vec<f32>,
+    // Rows per column as currently drawn into the texture.
+    drawn_rows: Vec<usize>,
+    texture: Handle<Texture>,
+}
+
+impl SnowCover {
+    fn column(&self, x: f32) -> Option<usize> {
+        let column = (x - self.left).floor();
+        if column >= 0.0 && (column as usize) < self.heights.len() {
+            Some(column as usize)
+        } else {
+            None
+        }
+    }
+
+    fn lands_on(&self, position: Vec2) -> bool {
+        match self.column(position.x) {
+            Some(column) => {
+                position.y >= self.bottom && position.y <= self.bottom + self.heights[column]
+            }
+            None => false,
+        }
+    }
+
+    fn deposit(&mut self, x: f32) {
+        if let Some(column) = self.column(x) {
+            let from = column.saturating_sub(SNOW_SPREAD);
+            let to = (column + SNOW_SPREAD + 1).min(self.heights.len());
+            for height in &mut self.heights[from..to] {
+                *height = (*height + SNOW_PER_FLAKE).min(MAX_SNOW_DEPTH);
+            }
+        }
+    }
+
+    fn stamp(&mut self, x: f32) {
+        if let Some(column) = self.column(x) {
+            let from = column.saturating_sub(FOOTPRINT_HALF_WIDTH);
+            let to = (column + FOOTPRINT_HALF_WIDTH + 1).min(self.heights.len());
+            for height in &mut self.heights[from..to] {
+                *height *= FOOTPRINT_DEPTH;
+            }
+        }
+    }
+
+    fn filled_rows(&self) -> Vec<usize> {
+        let rows = MAX_SNOW_DEPTH.ceil() as usize;
+        self.heights
+            .iter()
+            .map(|height| (height.round() as usize).min(rows))
+            .collect()
+    }
+
+    fn texture_data(&self) -> Vec<u8> {
+        let rows = MAX_SNOW_DEPTH.ceil() as usize;
+        let mut data = vec![0; self.heights.len() * rows * 4];
+        for (column, &filled) in self.drawn_rows.iter().enumerate() {
+            for row in 0..filled {
+                // Rows are stored top to bottom, the snow grows from the bottom.
+                let offset = ((rows - 1 - row) * self.heights.len() + column) * 4;
+                let color: [u8; 4] = if row + 1 == filled {
+                    [220, 225, 240, 255]
+                } else {
+                    [255, 255, 255, 255]
+                };
+                data[offset..offset + 4].copy_from_slice(&color);
+            }
+        }
+        data
+    }
+}
+
+pub fn init_snow_cover(
+    parent: &mut ChildBuilder,
+    surfaces: &[SnowSurface],
+    textures: &mut Assets<Texture>,
+    materials: &mut Assets<ColorMaterial>,
+) {
+    for surface in surfaces {
+        let width = (surface.right - surface.left).round().max(1.0) as usize;
+        let mut snow_cover = SnowCover {
+            left: surface.left,
+            bottom: surface.y,
+            heights: vec![INITIAL_SNOW_DEPTH; width],
+            drawn_rows: Vec::new(),
+            texture: Default::default(),
+        };
+        snow_cover.drawn_rows = snow_cover.filled_rows();
+        let texture = textures.add(Texture::new(
+            Extent3d::new(width as u32, MAX_SNOW_DEPTH.ceil() as u32, 1),
+            TextureDimension::D2,
+            snow_cover.texture_data(),
+            TextureFormat::Rgba8UnormSrgb,
+        ));
+        snow_cover.texture = texture.clone();
+
+        parent
+            .spawn_bundle(SpriteBundle {
+                material: materials.add(texture.into()),
+                transform: Transform::from_translation(Vec3::new(
+                    surface.left + width as f32 / 2.0,
+                    surface.y + MAX_SNOW_DEPTH.ceil() / 2.0,
+                    0.6,
+                )),
+                ..Default::default()
+            })
+            .insert(snow_cover);
+    }
+}
+
+// Flakes in the area at `SNOWFLAKE_DENSITY`, the density of a weather intensity of 1.
+pub fn baseline_snowflake_amount(area: &Rect<f32>) -> usize {
+    ((area.top - area.bottom).abs() * (area.right - area.left).abs() * SNOWFLAKE_DENSITY).floor()
+        as usize
+}
+
+pub fn snowflake_amount(area: &Rect<f32>) -> usize {
+    (baseline_snowflake_amount(area) as f32 * MAX_INTENSITY).floor() as usize
+}
+
+pub fn snowflake_area(level_camera_boundary: &LevelCameraBoundary) -> Rect<f32> {
+    Rect {
+        left: level_camera_boundary.0.left - 10.0,
+        right: level_camera_boundary.0.right + 10.0,
+        bottom: level_camera_boundary.0.bottom - 10.0,
+        top: level_camera_boundary.0.top + 10.0,
+    }
+}
+
+pub fn spawn_snowflakes<R: Rng>(rng: &mut R, area: &Rect<f32>, amount: usize) -> Vec<Snowflake> {
+    (0..amount)
+        .map(|_| {
+            let origin = Vec2::new(
+                Uniform::new(area.left, area.right).sample(rng),
+                Uniform::new(area.bottom, area.top).sample(rng),
+            );
+            let size = Uniform::new(0.0, 1.0).sample(rng);
+            // Atlas sprites from largest to smallest: 0, then 1 and 2, then 3.
+            let index = if size > 0.8 {
+                0
+            } else if size > 0.3 {
+                Uniform::new_inclusive(1, 2).sample(rng)
+            } else {
+                3
+            };
+            Snowflake {
+                origin,
+                position: origin,
+                threshold: Uniform::new(0.0, MAX_INTENSITY).sample(rng),
+                phase: Uniform::new(0.0, std::f32::consts::TAU).sample(rng),
+                size,
+                index,
+            }
+        })
+        .collect()
+}
+
+fn respawn_snowflake<R: Rng>(snowflake: &mut Snowflake, area: &Rect<f32>, rng: &mut R) {
+    snowflake.origin.x = Uniform::new(area.left, area.right).sample(rng);
+    snowflake.origin.y = area.top;
+    snowflake.position = snowflake.origin;
+}
+
+pub fn simulate_snowflakes<R: Rng>(
+    snowflakes: &mut [Snowflake],
+    layer: &SnowflakeLayer,
+    noise: &SnowflakeNoise,
+    snowfall: &Snowfall,
+    seconds: f32,
+    rng: &mut R,
+) {
+    let area = &snowfall.area;
+    let width = area.right - area.left;
+    let scroll = Vec2::new(FIELD_SCROLL_X, FIELD_SCROLL_Y) * seconds * snowfall.turbulence;
+
+    for snowflake in snowflakes.iter_mut() {
+        let weight = snowflake.weight();
+        snowflake.origin += snowfall.velocity * layer.speed * weight * TIME_STEP;
+        if snowflake.origin.y < area.bottom {
+            respawn_snowflake(snowflake, area, rng);
+        }
+        if snowflake.origin.x < area.left {
+            snowflake.origin.x += width;
+        } else if snowflake.origin.x > area.right {
+            snowflake.origin.x -= width;
+        }
+
+        let flow = noise.sample(snowflake.origin + scroll);
+        let sway = (seconds * SWAY_FREQUENCY + snowflake.phase).sin() * SWAY_AMPLITUDE;
+        snowflake.position = snowflake.origin
+            + Vec2::new(flow.x * DISPLACEMENT_X + sway, flow.y * DISPLACEMENT_Y)
+                * snowfall.turbulence
+                * layer.drift
+                * weight;
+    }
+}
+
+fn visible_sprites<'a>(
+    snowflakes: &'a [Snowflake],
+    layer: &'a SnowflakeLayer,
+    intensity: f32,
+) -> impl Iterator<Item = (Vec2, f32, usize)> + 'a {
+    snowflakes
+        .iter()
+        .filter(move |snowflake| snowflake.threshold < intensity)
+        .map(move |snowflake| (snowflake.position, layer.scale, snowflake.index))
+}
+
+// All flakes of a layer share one mesh.
+pub fn build_snowflake_mesh(
+    snowflakes: &[Snowflake],
+    layer: &SnowflakeLayer,
+    intensity: f32,
+    atlas: &TextureAtlas,
+) -> Mesh {
+    build_sprite_batch(visible_sprites(snowflakes, layer, intensity), atlas)
+}
+
+pub fn fill_snowflake_mesh(
+    mesh: &mut Mesh,
+    snowflakes: &[Snowflake],
+    layer: &SnowflakeLayer,
+    intensity: f32,
+    atlas: &TextureAtlas,
+) {
+    fill_sprite_batch(mesh, visible_sprites(snowflakes, layer, intensity), atlas);
+}
 
 pub fn init_snowflakes(
     parent: &mut ChildBuilder,
     level_camera_boundary: &LevelCameraBoundary,
-    santa_assets: &Res<SantaAssets>,
+    santa_assets: &SantaAssets,
+    texture_atlases: &Assets<TextureAtlas>,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<ColorMaterial>,
+    game_rng: &mut GameRng,
 ) {
-    let target_amount = (((level_camera_boundary.0.top - level_camera_boundary.0.bottom).abs()
-        + 20.0)
-        * ((level_camera_boundary.0.right - level_camera_boundary.0.left).abs() + 20.0)
-        * SNOWFLAKE_DENSITY)
-        .abs()
-        .floor() as usize;
-
-    let mut rng = thread_rng();
-
-    for _ in 0..target_amount {
-        let mut sprite = TextureAtlasSprite::default();
-        sprite.index = Uniform::new(0, 4).sample(&mut rng);
-
-        let position = Position(Vec2::new(
-            Uniform::new(
-                level_camera_boundary.0.left - 10.0,
-                level_camera_boundary.0.right + 10.0,
-            )
-            .sample(&mut rng),
-            Uniform::new(
-                level_camera_boundary.0.bottom - 10.0,
-                level_camera_boundary.0.top + 10.0,
-            )
-            .sample(&mut rng),
-        ));
-        let translation = Vec3::new(position.0.x, position.0.y, 0.5);
+    let atlas = texture_atlases.get(&santa_assets.snowflakes).unwrap();
+    let area = snowflake_area(level_camera_boundary);
+    let amount = snowflake_amount(&area) as f32;
+
+    for layer in SNOWFLAKE_LAYERS.iter() {
+        let flakes = spawn_snowflakes(
+            game_rng.stream(RNG_STREAM),
+            &area,
+            (amount * layer.share) as usize,
+        );
+        let mesh = build_snowflake_mesh(&flakes, layer, MAX_INTENSITY, atlas);
 
         parent
-            .spawn_bundle(SpriteSheetBundle {
-                texture_atlas: santa_assets.snowflakes.clone(),
-                sprite,
-                transform: Transform::from_translation(translation),
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    size: Vec2::ONE,
+                    resize_mode: SpriteResizeMode::Manual,
+                    ..Default::default()
+                },
+                mesh: meshes.add(mesh),
+                material: materials.add(ColorMaterial {
+                    color: Color::rgba(1.0, 1.0, 1.0, layer.alpha),
+                    texture: Some(atlas.texture.clone()),
+                }),
+                transform: Transform::from_translation(Vec3::new(0.0, 0.0, layer.depth)),
                 ..Default::default()
             })
-            .insert(Snowflake(Vec2::new(position.0.x, position.0.y)))
-            .insert(position);
+            .insert(Snowflakes {
+                layer: *layer,
+                flakes,
+            });
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn update_snowflakes_system(
-    time: Res<Time>,
-    mut snowflakes_query: Query<(&mut Snowflake, &mut Position)>,
+    mut seconds: Local<f32>,
+    weather: Res<Weather>,
+    noise: Res<SnowflakeNoise>,
+    santa_assets: Res<SantaAssets>,
+    texture_atlases: Res<Assets<TextureAtlas>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut snowflakes_query: Query<(&mut Snowflakes, &Handle<Mesh>, &mut Visible)>,
+    mut snow_cover_query: Query<&mut SnowCover>,
     level_camera_boundary: Res<LevelCameraBoundary>,
+    mut game_rng: ResMut<GameRng>,
 ) {
-    let mut rng = thread_rng();
-    let noise_x = BasicMulti::new()
-        .set_seed(0)
-        .set_octaves(6)
-        .set_frequency(0.02)
-        .set_lacunarity(1.5)
-        .set_persistence(0.7);
-    let noise_y = BasicMulti::new()
-        .set_seed(432627)
-        .set_octaves(6)
-        .set_frequency(0.02)
-        .set_lacunarity(1.5)
-        .set_persistence(0.7);
-
-    for (mut snowflake, mut position) in snowflakes_query.iter_mut() {
-        snowflake.0.y -= 10.0 * TIME_STEP;
-        if snowflake.0.y < level_camera_boundary.0.bottom - 10.0 {
-            snowflake.0.x = Uniform::new(
-                level_camera_boundary.0.left - 10.0,
-                level_camera_boundary.0.right + 10.0,
-            )
-            .sample(&mut rng);
-            snowflake.0.y = level_camera_boundary.0.top + 10.0;
+    let atlas = match texture_atlases.get(&santa_assets.snowflakes) {
+        Some(atlas) => atlas,
+        None => return,
+    };
+    let rng = game_rng.stream(RNG_STREAM);
+    *seconds += TIME_STEP;
+    let intensity = weather.state.intensity;
+    let snowfall = Snowfall {
+        area: snowflake_area(&level_camera_boundary),
+        velocity: weather.wind() - Vec2::new(0.0, SNOWFLAKE_FALL_SPEED),
+        turbulence: 0.7 + weather.state.gustiness,
+    };
+
+    for (mut snowflakes, mesh, mut visible) in snowflakes_query.iter_mut() {
+        let Snowflakes { layer, flakes } = &mut *snowflakes;
+        simulate_snowflakes(flakes, layer, &noise, &snowfall, *seconds, rng);
+
+        for snowflake in flakes
+            .iter_mut()
+            .filter(|snowflake| layer.lands && snowflake.threshold < intensity)
+        {
+            for mut snow_cover in snow_cover_query.iter_mut() {
+                if snow_cover.lands_on(snowflake.position) {
+                    snow_cover.deposit(snowflake.position.x);
+                    respawn_snowflake(snowflake, &snowfall.area, rng);
+                    break;
+                }
+            }
+        }
+
+        // An empty mesh has no vertex buffer to bind, so hide the batch instead.
+        visible.is_visible = flakes
+            .iter()
+            .any(|snowflake| snowflake.threshold < intensity);
+        if visible.is_visible {
+            if let Some(mesh) = meshes.get_mut(mesh) {
+                fill_snowflake_mesh(mesh, flakes, layer, intensity, atlas);
+            }
         }
+    }
+}
 
-        let displacement_x = noise_x.get([
-            snowflake.0.x as f64,
-            snowflake.0.y as f64,
-            time.seconds_since_startup() * 2.0,
-        ]) as f32
-            * 40.0;
-        let displacement_y = noise_y.get([
-            snowflake.0.x as f64,
-            snowflake.0.y as f64,
-            time.seconds_since_startup() * 2.0,
-        ]) as f32
-            * 25.0;
+fn footprints_system(
+    santa_query: Query<(&Position, &SpriteBoundary), With<Santa>>,
+    mut snow_cover_query: Query<&mut SnowCover>,
+    mut footstep_events: EventReader<Footstep>,
+) {
+    for footstep in footstep_events.iter() {
+        let (position, sprite_boundary) = match santa_query.get(footstep.0) {
+            Ok(santa) => santa,
+            Err(_) => continue,
+        };
+        let feet = position.0.y + sprite_boundary.0.bottom;
 
-        position.0.x = snowflake.0.x + displacement_x;
-        position.0.y = snowflake.0.y + displacement_y;
+        for mut snow_cover in snow_cover_query.iter_mut() {
+            if (feet - snow_cover.bottom).abs() <= FOOTPRINT_TOLERANCE {
+                snow_cover.stamp(position.0.x);
+            }
+        }
+    }
+}
+
+fn update_snow_cover_system(
+    mut textures: ResMut<Assets<Texture>>,
+    mut snow_cover_query: Query<&mut SnowCover>,
+) {
+    // Heights only change through landing flakes and footprints.
+    for mut snow_cover in snow_cover_query.iter_mut() {
+        // Touching the texture re-uploads it, so only do that once a column gains or loses a row.
+        let filled_rows = snow_cover.filled_rows();
+        if filled_rows == snow_cover.drawn_rows {
+            continue;
+        }
+        snow_cover.drawn_rows = filled_rows;
+        if let Some(texture) = textures.get_mut(&snow_cover.texture) {
+            texture.data = snow_cover.texture_data();
+        }
     }
 }
 
@@ -108,11 +541,26 @@ pub struct SnowflakesPlugin;
 
 impl Plugin for SnowflakesPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_system(
-            update_snowflakes_system
-                .system()
-                .label("update_snowflakes")
-                .before("position_sprites"),
-        );
+        app.init_resource::<SnowflakeNoise>()
+            .add_system(
+                update_snowflakes_system
+                    .system()
+                    .label("update_snowflakes")
+                    .after("update_weather")
+                    .before("position_sprites"),
+            )
+            .add_system(
+                footprints_system
+                    .system()
+                    .label("footprints")
+                    .after("animate_santa"),
+            )
+            .add_system(
+                update_snow_cover_system
+                    .system()
+                    .label("update_snow_cover")
+                    .after("update_snowflakes")
+                    .after("footprints"),
+            );
     }
 }
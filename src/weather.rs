@@ -0,0 +1,239 @@
+use crate::assets::load_data;
+use crate::console::ConsoleAppExt;
+use crate::physics::{GroundState, Speed};
+use crate::player::Santa;
+use crate::rng::GameRng;
+use crate::TIME_STEP;
+use bevy::prelude::*;
+use noise::{NoiseFn, Perlin, Seedable};
+use rand::Rng;
+use serde_derive::Deserialize;
+use std::collections::HashMap;
+
+const GUST_FREQUENCY: f64 = 0.4;
+const GUST_STRENGTH: f32 = 2.0;
+const PUSH_THRESHOLD: f32 = 30.0;
+const PUSH_FACTOR: f32 = 0.5;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct WeatherState {
+    pub intensity: f32,
+    pub wind: Vec2,
+    pub gustiness: f32,
+}
+
+impl Default for WeatherState {
+    fn default() -> Self {
+        Self {
+            intensity: 1.0,
+            wind: Vec2::ZERO,
+            gustiness: 0.3,
+        }
+    }
+}
+
+impl WeatherState {
+    fn lerp(&self, other: &WeatherState, t: f32) -> WeatherState {
+        WeatherState {
+            intensity: self.intensity + (other.intensity - self.intensity) * t,
+            wind: self.wind.lerp(other.wind, t),
+            gustiness: self.gustiness + (other.gustiness - self.gustiness) * t,
+        }
+    }
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct WeatherData {
+    pub presets: HashMap<String, WeatherState>,
+    pub cycle: Vec<(String, f32)>,
+    pub transition: f32,
+    pub push_santa: bool,
+}
+
+#[derive(Default)]
+pub struct Weather {
+    pub state: WeatherState,
+    pub gust: f32,
+}
+
+impl Weather {
+    pub fn wind(&self) -> Vec2 {
+        self.state.wind * (1.0 + self.gust * GUST_STRENGTH)
+    }
+}
+
+pub struct ChangeWeather {
+    pub state: WeatherState,
+    pub transition: f32,
+    pub duration: Option<f32>,
+}
+
+struct WeatherTransition {
+    from: WeatherState,
+    to: WeatherState,
+    duration: f32,
+    elapsed: f32,
+}
+
+struct WeatherCycle {
+    step: usize,
+    remaining: Option<f32>,
+    transition: Option<WeatherTransition>,
+    // Seconds of game time, counted in frames so replays see the same gusts.
+    elapsed: f64,
+    noise: Perlin,
+}
+
+impl FromWorld for WeatherCycle {
+    fn from_world(world: &mut World) -> Self {
+        let mut game_rng = world.get_resource_mut::<GameRng>().unwrap();
+        Self {
+            step: 0,
+            remaining: None,
+            transition: None,
+            elapsed: 0.0,
+            noise: Perlin::new().set_seed(game_rng.stream("weather").gen()),
+        }
+    }
+}
+
+fn start_transition(weather: &Weather, cycle: &mut WeatherCycle, to: WeatherState, duration: f32) {
+    cycle.transition = Some(WeatherTransition {
+        from: weather.state.clone(),
+        to,
+        duration,
+        elapsed: 0.0,
+    });
+}
+
+fn init_weather_system(
+    weather_data: Res<WeatherData>,
+    mut weather: ResMut<Weather>,
+    mut cycle: ResMut<WeatherCycle>,
+) {
+    if let Some((preset, duration)) = weather_data.cycle.first() {
+        if let Some(state) = weather_data.presets.get(preset) {
+            weather.state = state.clone();
+        }
+        cycle.remaining = Some(*duration);
+    }
+}
+
+fn update_weather_system(
+    weather_data: Res<WeatherData>,
+    mut weather: ResMut<Weather>,
+    mut cycle: ResMut<WeatherCycle>,
+    mut change_weather_events: EventReader<ChangeWeather>,
+) {
+    let delta = TIME_STEP;
+    cycle.elapsed += delta as f64;
+
+    for change_weather in change_weather_events.iter() {
+        start_transition(
+            &weather,
+            &mut cycle,
+            change_weather.state.clone(),
+            change_weather.transition,
+        );
+        cycle.remaining = change_weather.duration;
+    }
+
+    if let Some(remaining) = &mut cycle.remaining {
+        *remaining -= delta;
+        if *remaining <= 0.0 && !weather_data.cycle.is_empty() {
+            cycle.step = (cycle.step + 1) % weather_data.cycle.len();
+            let (preset, duration) = &weather_data.cycle[cycle.step];
+            cycle.remaining = Some(*duration);
+            match weather_data.presets.get(preset) {
+                Some(state) => {
+                    start_transition(&weather, &mut cycle, state.clone(), weather_data.transition)
+                }
+                None => error!("Unknown weather preset {}", preset),
+            }
+        }
+    }
+
+    if let Some(transition) = &mut cycle.transition {
+        transition.elapsed += delta;
+        let t = (transition.elapsed / transition.duration.max(f32::EPSILON)).min(1.0);
+        weather.state = transition.from.lerp(&transition.to, t);
+        if t >= 1.0 {
+            cycle.transition = None;
+        }
+    }
+
+    let gust = cycle.noise.get([cycle.elapsed * GUST_FREQUENCY, 0.0]) as f32;
+    weather.gust = weather.state.gustiness * (gust * 0.5 + 0.5);
+}
+
+fn wind_push_system(
+    weather: Res<Weather>,
+    weather_data: Res<WeatherData>,
+    mut santa_query: Query<(&mut Speed, &GroundState), With<Santa>>,
+) {
+    if !weather_data.push_santa {
+        return;
+    }
+
+    let wind = weather.wind().x;
+    if wind.abs() < PUSH_THRESHOLD {
+        return;
+    }
+    for (mut speed, ground_state) in santa_query.iter_mut() {
+        if ground_state.on_ground {
+            speed.0.x += wind * PUSH_FACTOR * TIME_STEP;
+        }
+    }
+}
+
+fn weather_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let weather_data = world.get_resource::<WeatherData>().unwrap();
+    let preset = *args.first().ok_or_else(|| {
+        let mut presets = weather_data.presets.keys().cloned().collect::<Vec<_>>();
+        presets.sort();
+        format!("Expected one of {}", presets.join(", "))
+    })?;
+    let change_weather = ChangeWeather {
+        state: weather_data
+            .presets
+            .get(preset)
+            .ok_or_else(|| format!("Unknown weather preset {}", preset))?
+            .clone(),
+        transition: weather_data.transition,
+        duration: None,
+    };
+
+    world
+        .get_resource_mut::<Events<ChangeWeather>>()
+        .unwrap()
+        .send(change_weather);
+    Ok(format!("Changing weather to {}", preset))
+}
+
+pub struct WeatherPlugin;
+
+impl Plugin for WeatherPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(load_data::<WeatherData>("weather.ron").unwrap_or_default())
+            .init_resource::<Weather>()
+            .init_resource::<WeatherCycle>()
+            .add_event::<ChangeWeather>()
+            .add_console_command(
+                "weather",
+                "weather PRESET - switch to a weather preset",
+                weather_command,
+            )
+            .add_startup_system(init_weather_system.system())
+            .add_system(update_weather_system.system().label("update_weather"))
+            .add_system(
+                wind_push_system
+                    .system()
+                    .label("wind_push")
+                    .after("update_weather")
+                    .after("control_santa")
+                    .before("move"),
+            );
+    }
+}
@@ -0,0 +1,92 @@
+use bevy::prelude::*;
+use santa_game::dialogue_graph::DialogueRunner;
+use santa_game::headless::HeadlessGame;
+use santa_game::levels::{LevelData, LevelPlayerBoundary, LevelState};
+use santa_game::physics::SpriteBoundary;
+use santa_game::player::Santa;
+
+fn landed_game() -> HeadlessGame {
+    let mut game = HeadlessGame::new(0);
+    game.step_seconds(1.0);
+    game
+}
+
+#[test]
+fn walking_right_then_pressing_f_enters_indoors() {
+    let mut game = landed_game();
+
+    game.hold(KeyCode::D, 10.0);
+    assert!(game.santa_position().x >= 200.0);
+    assert_eq!(game.level(), LevelState::Outside);
+
+    game.tap(KeyCode::F);
+    game.step(2);
+    assert_eq!(game.level(), LevelState::Indoors);
+}
+
+#[test]
+fn pressing_f_away_from_the_door_stays_outside() {
+    let mut game = landed_game();
+
+    game.tap(KeyCode::F);
+    game.step(2);
+    assert_eq!(game.level(), LevelState::Outside);
+}
+
+#[test]
+fn walking_left_stops_at_the_level_boundary() {
+    let mut game = landed_game();
+
+    game.hold(KeyCode::A, 3.0);
+    let boundary = game
+        .app
+        .world
+        .get_resource::<LevelPlayerBoundary>()
+        .unwrap()
+        .0;
+    let mut query = game
+        .app
+        .world
+        .query_filtered::<&SpriteBoundary, With<Santa>>();
+    let sprite_boundary = query.iter(&game.app.world).next().unwrap().0;
+    assert_eq!(
+        game.santa_position().x,
+        boundary.left - sprite_boundary.left
+    );
+}
+
+#[test]
+fn talking_to_the_elf_starts_a_conversation() {
+    let mut game = landed_game();
+
+    game.hold(KeyCode::D, 1.2);
+    game.tap(KeyCode::F);
+    let dialogue_runner = game.app.world.get_resource::<DialogueRunner>().unwrap();
+    assert!(dialogue_runner.is_running());
+}
+
+#[test]
+fn level_command_without_position_uses_the_level_spawn() {
+    let mut game = landed_game();
+
+    game.run_command("level indoors");
+    game.step(1);
+    assert_eq!(game.level(), LevelState::Indoors);
+
+    let spawn = game
+        .app
+        .world
+        .get_resource::<LevelData>()
+        .unwrap()
+        .get(&LevelState::Indoors)
+        .spawn;
+    let boundary = game
+        .app
+        .world
+        .get_resource::<LevelPlayerBoundary>()
+        .unwrap()
+        .0;
+    let position = game.santa_position();
+    assert!(position.x > boundary.left && position.x < boundary.right);
+    assert!((position.x - spawn.x).abs() < 1.0);
+}
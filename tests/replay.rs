@@ -0,0 +1,99 @@
+use bevy::prelude::*;
+use santa_game::cli::CliArgs;
+use santa_game::headless::HeadlessGame;
+use santa_game::levels::LevelState;
+use santa_game::weather::{Weather, WeatherData};
+
+fn step_tracked(game: &mut HeadlessGame, frames: usize, positions: &mut Vec<Vec2>) {
+    for _ in 0..frames {
+        game.step(1);
+        positions.push(game.santa_position());
+    }
+}
+
+#[test]
+fn replay_reproduces_santa_positions_frame_for_frame() {
+    let mut game = HeadlessGame::new(42);
+    game.record();
+
+    let mut recorded = Vec::new();
+    step_tracked(&mut game, 60, &mut recorded);
+    game.press(KeyCode::D);
+    step_tracked(&mut game, 90, &mut recorded);
+    game.press(KeyCode::Space);
+    step_tracked(&mut game, 2, &mut recorded);
+    game.release(KeyCode::Space);
+    step_tracked(&mut game, 60, &mut recorded);
+    game.release(KeyCode::D);
+    game.press(KeyCode::A);
+    step_tracked(&mut game, 45, &mut recorded);
+    game.release(KeyCode::A);
+    step_tracked(&mut game, 30, &mut recorded);
+
+    let recording = game.recording().unwrap();
+    assert_eq!(recording.seed, 42);
+    assert_eq!(recording.frames.len(), recorded.len());
+
+    let mut replay = HeadlessGame::replay(recording);
+    let mut replayed = Vec::new();
+    step_tracked(&mut replay, recorded.len(), &mut replayed);
+    assert_eq!(recorded, replayed);
+}
+
+// Calm for a second, then a storm strong enough to push Santa.
+fn with_short_storm(game: &mut HeadlessGame) {
+    let mut weather_data = game.app.world.get_resource_mut::<WeatherData>().unwrap();
+    weather_data.cycle = vec![("calm".to_owned(), 1.0), ("storm".to_owned(), 30.0)];
+    weather_data.transition = 1.0;
+    weather_data.push_santa = true;
+}
+
+#[test]
+fn replay_reproduces_santa_positions_across_a_weather_transition() {
+    let mut game = HeadlessGame::new(7);
+    with_short_storm(&mut game);
+    game.record();
+
+    let mut recorded = Vec::new();
+    step_tracked(&mut game, 60, &mut recorded);
+    game.press(KeyCode::D);
+    step_tracked(&mut game, 150, &mut recorded);
+    game.release(KeyCode::D);
+    step_tracked(&mut game, 90, &mut recorded);
+
+    let recording = game.recording().unwrap();
+    let mut replay = HeadlessGame::replay(recording);
+    with_short_storm(&mut replay);
+    let mut replayed = Vec::new();
+    step_tracked(&mut replay, recorded.len(), &mut replayed);
+
+    let weather = replay.app.world.get_resource::<Weather>().unwrap();
+    assert!(weather.wind().x < -30.0);
+    assert_eq!(recorded, replayed);
+}
+
+#[test]
+fn replay_starts_with_the_recorded_arguments() {
+    let cli_args = CliArgs {
+        level: Some(LevelState::Indoors),
+        skip_intro: true,
+        ..Default::default()
+    };
+    let mut game = HeadlessGame::with_args(3, cli_args);
+    game.record();
+
+    let mut recorded = Vec::new();
+    step_tracked(&mut game, 30, &mut recorded);
+    game.press(KeyCode::D);
+    step_tracked(&mut game, 60, &mut recorded);
+    game.release(KeyCode::D);
+
+    let recording = game.recording().unwrap();
+    assert_eq!(recording.level.as_deref(), Some("indoors"));
+
+    let mut replay = HeadlessGame::replay(recording);
+    let mut replayed = Vec::new();
+    step_tracked(&mut replay, recorded.len(), &mut replayed);
+    assert_eq!(replay.level(), LevelState::Indoors);
+    assert_eq!(recorded, replayed);
+}